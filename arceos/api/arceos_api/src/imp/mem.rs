@@ -10,6 +10,23 @@ cfg_alloc! {
     pub fn ax_dealloc(ptr: NonNull<u8>, layout: Layout) {
         axalloc::global_allocator().dealloc(ptr, layout)
     }
+
+    /// Usage of the global byte allocator, in bytes.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct AxHeapStats {
+        pub total: usize,
+        pub used: usize,
+        pub available: usize,
+    }
+
+    pub fn ax_heap_stats() -> AxHeapStats {
+        let (total, used, available) = axalloc::global_allocator().byte_usage();
+        AxHeapStats {
+            total,
+            used,
+            available,
+        }
+    }
 }
 
 cfg_dma! {
@@ -75,6 +75,17 @@ pub mod mem {
         pub unsafe fn ax_dealloc(ptr: NonNull<u8>, layout: Layout);
     }
 
+    define_api_type! {
+        @cfg "alloc";
+        pub type AxHeapStats;
+    }
+
+    define_api! {
+        @cfg "alloc";
+        /// Returns the usage of the global byte allocator.
+        pub fn ax_heap_stats() -> AxHeapStats;
+    }
+
     define_api_type! {
         @cfg "dma";
         pub type DMAInfo;
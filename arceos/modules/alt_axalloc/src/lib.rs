@@ -5,7 +5,7 @@ extern crate log;
 extern crate alloc;
 
 use allocator::{AllocResult, BaseAllocator, ByteAllocator, PageAllocator};
-use bump_allocator::EarlyAllocator;
+use bump_allocator::{AllocStats, EarlyAllocator, StatsReport};
 use core::alloc::{GlobalAlloc, Layout};
 use core::ptr::NonNull;
 use kspin::SpinNoIrq;
@@ -81,6 +81,11 @@ impl GlobalAllocator {
     pub fn available_pages(&self) -> usize {
         self.inner.lock().available_pages()
     }
+
+    /// Returns a snapshot of the allocator statistics.
+    pub fn stats(&self) -> StatsReport {
+        self.inner.lock().report()
+    }
 }
 
 unsafe impl GlobalAlloc for GlobalAllocator {
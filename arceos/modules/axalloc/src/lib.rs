@@ -167,6 +167,17 @@ impl GlobalAllocator {
         self.balloc.lock().available_bytes()
     }
 
+    /// Returns the total, allocated and available bytes of the byte
+    /// allocator, read under a single lock.
+    pub fn byte_usage(&self) -> (usize, usize, usize) {
+        let balloc = self.balloc.lock();
+        (
+            balloc.total_bytes(),
+            balloc.used_bytes(),
+            balloc.available_bytes(),
+        )
+    }
+
     /// Returns the number of allocated pages in the page allocator.
     pub fn used_pages(&self) -> usize {
         self.palloc.lock().used_pages()
@@ -0,0 +1,168 @@
+use core::alloc::Layout;
+use core::cell::RefCell;
+use core::ptr::NonNull;
+
+use allocator::{AllocResult, ByteAllocator};
+
+/// A typed arena over a byte allocator, typically a bump allocator, handing
+/// out references that live as long as the borrow of the allocator.
+///
+/// Values are never dropped: the arena is meant for plain data such as the
+/// nodes of a linked structure. A value owning other resources leaks them,
+/// as if it were wrapped in [`ManuallyDrop`](core::mem::ManuallyDrop). The
+/// memory itself goes back to the allocator only through its own means,
+/// e.g. when the bytes area of an [`EarlyAllocator`] is reset.
+///
+/// References cannot outlive the allocator borrow:
+///
+/// ```compile_fail
+/// use bump_allocator::{Arena, EarlyAllocator};
+///
+/// let value;
+/// {
+///     let mut alloc = EarlyAllocator::<0x1000>::new();
+///     let arena = Arena::new(&mut alloc);
+///     value = arena.alloc(1u32);
+/// }
+/// assert_eq!(*value, 1);
+/// ```
+///
+/// [`EarlyAllocator`]: crate::EarlyAllocator
+pub struct Arena<'a, A> {
+    alloc: RefCell<&'a mut A>,
+}
+
+impl<'a, A: ByteAllocator> Arena<'a, A> {
+    /// Creates an arena allocating from `alloc`.
+    pub fn new(alloc: &'a mut A) -> Self {
+        Self {
+            alloc: RefCell::new(alloc),
+        }
+    }
+
+    /// Moves `value` into the arena.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the allocator is out of memory, see [`try_alloc`].
+    ///
+    /// [`try_alloc`]: Arena::try_alloc
+    pub fn alloc<T>(&self, value: T) -> &'a mut T {
+        self.try_alloc(value).expect("arena out of memory")
+    }
+
+    /// Moves `value` into the arena, or returns an error if the allocator
+    /// is out of memory.
+    pub fn try_alloc<T>(&self, value: T) -> AllocResult<&'a mut T> {
+        let ptr = self.alloc_layout(Layout::new::<T>())?.cast::<T>();
+        // SAFETY: The memory is fresh, fits `T`, and stays allocated for 'a.
+        unsafe {
+            ptr.as_ptr().write(value);
+            Ok(&mut *ptr.as_ptr())
+        }
+    }
+
+    /// Copies `src` into the arena.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the allocator is out of memory.
+    pub fn alloc_slice_copy<T: Copy>(&self, src: &[T]) -> &'a mut [T] {
+        let layout = Layout::array::<T>(src.len()).expect("slice too large");
+        let ptr = self.alloc_layout(layout).expect("arena out of memory");
+        let ptr = ptr.cast::<T>().as_ptr();
+        // SAFETY: The memory is fresh, fits `src.len()` values of `T`, and
+        // stays allocated for 'a.
+        unsafe {
+            ptr.copy_from_nonoverlapping(src.as_ptr(), src.len());
+            core::slice::from_raw_parts_mut(ptr, src.len())
+        }
+    }
+
+    /// Copies `s` into the arena.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the allocator is out of memory.
+    pub fn alloc_str(&self, s: &str) -> &'a str {
+        let bytes = self.alloc_slice_copy(s.as_bytes());
+        // SAFETY: The bytes are copied from a `str`.
+        unsafe { core::str::from_utf8_unchecked(bytes) }
+    }
+
+    /// Allocates memory for `layout`, without asking the allocator for
+    /// zero-sized layouts.
+    fn alloc_layout(&self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        if layout.size() == 0 {
+            // A dangling pointer which is suitably aligned.
+            return Ok(unsafe { NonNull::new_unchecked(layout.align() as *mut u8) });
+        }
+        self.alloc.borrow_mut().alloc(layout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EarlyAllocator;
+    use allocator::BaseAllocator;
+
+    #[repr(align(4096))]
+    struct Region([u8; 0x2000]);
+
+    struct Node<'a> {
+        name: &'a str,
+        weight: u64,
+        children: &'a [&'a Node<'a>],
+    }
+
+    fn total_weight(node: &Node<'_>) -> u64 {
+        node.weight + node.children.iter().map(|c| total_weight(c)).sum::<u64>()
+    }
+
+    #[test]
+    fn test_tree() {
+        let mut region = Region([0; 0x2000]);
+        let mut alloc = EarlyAllocator::<0x1000>::new();
+        alloc.init(region.0.as_mut_ptr() as usize, region.0.len());
+        let arena = Arena::new(&mut alloc);
+
+        let leaf = |name: &str, weight| {
+            &*arena.alloc(Node {
+                name: arena.alloc_str(name),
+                weight,
+                children: &[],
+            })
+        };
+        let left = arena.alloc(Node {
+            name: arena.alloc_str("left"),
+            weight: 2,
+            children: arena.alloc_slice_copy(&[leaf("a", 3), leaf("b", 4)]),
+        });
+        // Values are handed out mutably.
+        left.weight = 20;
+        let root = arena.alloc(Node {
+            name: arena.alloc_str("root"),
+            weight: 1,
+            children: arena.alloc_slice_copy(&[&*left, leaf("c", 5)]),
+        });
+
+        assert_eq!(total_weight(root), 1 + 20 + 3 + 4 + 5);
+        let names: [&str; 2] = [root.children[0].name, root.children[1].name];
+        assert_eq!(names, ["left", "c"]);
+        assert_eq!(root.children[0].children[1].name, "b");
+
+        // Zero-sized and over-aligned values are laid out as requested.
+        let unit = arena.alloc(());
+        assert_eq!(unit as *mut () as usize % core::mem::align_of::<()>(), 0);
+        #[repr(align(64))]
+        struct Aligned(u8);
+        let aligned = arena.alloc(Aligned(7));
+        assert_eq!(aligned as *mut Aligned as usize % 64, 0);
+        assert_eq!(aligned.0, 7);
+        let empty: &mut [u64] = arena.alloc_slice_copy(&[]);
+        assert!(empty.is_empty());
+
+        assert!(arena.try_alloc([0u8; 0x2000]).is_err());
+    }
+}
@@ -0,0 +1,327 @@
+use core::alloc::Layout;
+use core::ptr::NonNull;
+
+use allocator::{AllocError, AllocResult, BaseAllocator, ByteAllocator};
+
+use crate::AllocStats;
+
+/// An allocator that can tell whether a pointer comes from its memory.
+pub trait OwnedRegion {
+    /// Returns `true` if `ptr` lies in memory managed by the allocator.
+    fn owns(&self, ptr: NonNull<u8>) -> bool;
+}
+
+/// A byte allocator that remembers the regions given to it, to implement
+/// [`OwnedRegion`] for allocators that do not track them, such as TLSF.
+///
+/// Holds up to `REGIONS` regions: `init` plus `REGIONS - 1` calls to
+/// `add_memory`.
+pub struct Pool<A, const REGIONS: usize = 4> {
+    inner: A,
+    regions: [(usize, usize); REGIONS],
+    len: usize,
+}
+
+impl<A, const REGIONS: usize> Pool<A, REGIONS> {
+    /// Wraps an allocator which has not been given any memory yet.
+    pub const fn new(inner: A) -> Self {
+        Self {
+            inner,
+            regions: [(0, 0); REGIONS],
+            len: 0,
+        }
+    }
+
+    /// Returns the wrapped allocator.
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+}
+
+impl<A, const REGIONS: usize> OwnedRegion for Pool<A, REGIONS> {
+    fn owns(&self, ptr: NonNull<u8>) -> bool {
+        let addr = ptr.as_ptr() as usize;
+        self.regions[..self.len]
+            .iter()
+            .any(|&(start, end)| (start..end).contains(&addr))
+    }
+}
+
+impl<A: BaseAllocator, const REGIONS: usize> BaseAllocator for Pool<A, REGIONS> {
+    fn init(&mut self, start: usize, size: usize) {
+        self.inner.init(start, size);
+        self.regions[0] = (start, start + size);
+        self.len = 1;
+    }
+
+    fn add_memory(&mut self, start: usize, size: usize) -> AllocResult {
+        if self.len == REGIONS {
+            return Err(AllocError::NoMemory);
+        }
+        self.inner.add_memory(start, size)?;
+        self.regions[self.len] = (start, start + size);
+        self.len += 1;
+        Ok(())
+    }
+}
+
+impl<A: ByteAllocator, const REGIONS: usize> ByteAllocator for Pool<A, REGIONS> {
+    fn alloc(&mut self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        self.inner.alloc(layout)
+    }
+
+    fn dealloc(&mut self, pos: NonNull<u8>, layout: Layout) {
+        self.inner.dealloc(pos, layout)
+    }
+
+    fn total_bytes(&self) -> usize {
+        self.inner.total_bytes()
+    }
+
+    fn used_bytes(&self) -> usize {
+        self.inner.used_bytes()
+    }
+
+    fn available_bytes(&self) -> usize {
+        self.inner.available_bytes()
+    }
+}
+
+/// Which allocator of a [`ChainAllocator`] receives new memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddMemoryTo {
+    First,
+    Second,
+}
+
+/// Two byte allocators used as one: allocations try `A` first and fall
+/// back to `B`, and deallocations go to the allocator owning the pointer.
+/// Deallocating a pointer that neither owns panics, even in release builds,
+/// rather than corrupting `B`.
+///
+/// `init` and `add_memory` go to `B` by default, see [`set_policy`].
+/// Initialize the other allocator before chaining it.
+///
+/// [`set_policy`]: ChainAllocator::set_policy
+pub struct ChainAllocator<A, B> {
+    first: A,
+    second: B,
+    policy: AddMemoryTo,
+    peak: usize,
+    alloc_count: usize,
+    fail_count: usize,
+}
+
+impl<A, B> ChainAllocator<A, B> {
+    /// Chains `first` and its fallback `second`.
+    pub const fn new(first: A, second: B) -> Self {
+        Self {
+            first,
+            second,
+            policy: AddMemoryTo::Second,
+            peak: 0,
+            alloc_count: 0,
+            fail_count: 0,
+        }
+    }
+
+    /// Sets the allocator receiving new memory.
+    pub fn set_policy(&mut self, policy: AddMemoryTo) {
+        self.policy = policy;
+    }
+
+    /// Returns the allocator tried first.
+    pub fn first(&self) -> &A {
+        &self.first
+    }
+
+    /// Returns the fallback allocator.
+    pub fn second(&self) -> &B {
+        &self.second
+    }
+}
+
+impl<A: OwnedRegion, B: OwnedRegion> OwnedRegion for ChainAllocator<A, B> {
+    fn owns(&self, ptr: NonNull<u8>) -> bool {
+        self.first.owns(ptr) || self.second.owns(ptr)
+    }
+}
+
+impl<A: BaseAllocator, B: BaseAllocator> BaseAllocator for ChainAllocator<A, B> {
+    fn init(&mut self, start: usize, size: usize) {
+        match self.policy {
+            AddMemoryTo::First => self.first.init(start, size),
+            AddMemoryTo::Second => self.second.init(start, size),
+        }
+    }
+
+    fn add_memory(&mut self, start: usize, size: usize) -> AllocResult {
+        match self.policy {
+            AddMemoryTo::First => self.first.add_memory(start, size),
+            AddMemoryTo::Second => self.second.add_memory(start, size),
+        }
+    }
+}
+
+impl<A, B> ByteAllocator for ChainAllocator<A, B>
+where
+    A: ByteAllocator + OwnedRegion,
+    B: ByteAllocator + OwnedRegion,
+{
+    fn alloc(&mut self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        let result = self
+            .first
+            .alloc(layout)
+            .or_else(|_| self.second.alloc(layout));
+        if result.is_ok() {
+            self.alloc_count += 1;
+            self.peak = self.peak.max(self.used_bytes());
+        } else {
+            self.fail_count += 1;
+        }
+        result
+    }
+
+    fn dealloc(&mut self, pos: NonNull<u8>, layout: Layout) {
+        if self.first.owns(pos) {
+            self.first.dealloc(pos, layout)
+        } else {
+            assert!(self.second.owns(pos), "dealloc of a foreign pointer");
+            self.second.dealloc(pos, layout)
+        }
+    }
+
+    fn total_bytes(&self) -> usize {
+        self.first.total_bytes() + self.second.total_bytes()
+    }
+
+    fn used_bytes(&self) -> usize {
+        self.first.used_bytes() + self.second.used_bytes()
+    }
+
+    fn available_bytes(&self) -> usize {
+        self.first.available_bytes() + self.second.available_bytes()
+    }
+}
+
+/// Sizes add up over both allocators. Counts are those of the chain: an
+/// allocation served by the fallback is one success, not a failure.
+impl<A, B> AllocStats for ChainAllocator<A, B>
+where
+    A: ByteAllocator + OwnedRegion,
+    B: ByteAllocator + OwnedRegion,
+{
+    fn total(&self) -> usize {
+        self.total_bytes()
+    }
+
+    fn used(&self) -> usize {
+        self.used_bytes()
+    }
+
+    fn peak(&self) -> usize {
+        self.peak
+    }
+
+    fn alloc_count(&self) -> usize {
+        self.alloc_count
+    }
+
+    fn fail_count(&self) -> usize {
+        self.fail_count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use allocator::TlsfByteAllocator;
+
+    #[repr(align(4096))]
+    struct Region([u8; 0x2000]);
+
+    fn pool(region: &mut Region) -> Pool<TlsfByteAllocator> {
+        let mut pool = Pool::new(TlsfByteAllocator::new());
+        pool.init(region.0.as_mut_ptr() as usize, region.0.len());
+        pool
+    }
+
+    #[test]
+    fn test_routing() {
+        let (mut a, mut b) = (Region([0; 0x2000]), Region([0; 0x2000]));
+        let mut chain = ChainAllocator::new(pool(&mut a), pool(&mut b));
+        let layout = Layout::from_size_align(0x400, 8).unwrap();
+
+        // Fill the first pool, then spill over into the second until both
+        // are exhausted.
+        let mut blocks = [None; 16];
+        let mut count = 0;
+        while let Ok(ptr) = chain.alloc(layout) {
+            assert!(chain.owns(ptr));
+            blocks[count] = Some(ptr);
+            count += 1;
+        }
+        // Spilling over into the second pool is a success, not a failure.
+        assert_eq!((chain.alloc_count(), chain.fail_count()), (count, 1));
+        let in_first = blocks[..count]
+            .iter()
+            .filter(|ptr| chain.first().owns(ptr.unwrap()))
+            .count();
+        let in_second = count - in_first;
+        assert!(in_first > 0 && in_second > 0);
+        // The first pool is full before the second one is used.
+        assert!(blocks[..in_first]
+            .iter()
+            .all(|ptr| chain.first().owns(ptr.unwrap())));
+        assert_eq!(chain.first().used_bytes(), in_first * 0x400);
+        assert_eq!(chain.second().used_bytes(), in_second * 0x400);
+
+        // Frees go back where they came from.
+        let last = blocks[count - 1].take().unwrap();
+        assert!(chain.second().owns(last) && !chain.first().owns(last));
+        chain.dealloc(last, layout);
+        assert_eq!(chain.second().used_bytes(), (in_second - 1) * 0x400);
+        let first = blocks[0].take().unwrap();
+        chain.dealloc(first, layout);
+        assert_eq!(chain.first().used_bytes(), (in_first - 1) * 0x400);
+        assert_eq!(chain.used(), (count - 2) * 0x400);
+        assert_eq!(chain.peak(), count * 0x400);
+
+        // The freed block in the first pool is reused first.
+        let ptr = chain.alloc(layout).unwrap();
+        assert!(chain.first().owns(ptr));
+    }
+
+    #[test]
+    fn test_chain_stats() {
+        let (mut a, mut b) = (Region([0; 0x2000]), Region([0; 0x2000]));
+        let mut chain = ChainAllocator::new(pool(&mut a), pool(&mut b));
+        crate::tests::exercise(&mut chain);
+    }
+
+    #[test]
+    #[should_panic(expected = "dealloc of a foreign pointer")]
+    fn test_foreign_dealloc() {
+        let (mut a, mut b) = (Region([0; 0x2000]), Region([0; 0x2000]));
+        let mut chain = ChainAllocator::new(pool(&mut a), pool(&mut b));
+        let layout = Layout::from_size_align(8, 8).unwrap();
+        chain.dealloc(NonNull::dangling(), layout);
+    }
+
+    #[test]
+    fn test_add_memory_policy() {
+        let (mut a, mut b) = (Region([0; 0x2000]), Region([0; 0x2000]));
+        let (mut c, mut d) = (Region([0; 0x2000]), Region([0; 0x2000]));
+        let mut chain = ChainAllocator::new(pool(&mut a), pool(&mut b));
+        chain.add_memory(c.0.as_mut_ptr() as usize, 0x2000).unwrap();
+        assert_eq!(chain.first().total_bytes(), 0x2000);
+        assert_eq!(chain.second().total_bytes(), 0x4000);
+        assert!(chain.second().owns(NonNull::new(c.0.as_mut_ptr()).unwrap()));
+
+        chain.set_policy(AddMemoryTo::First);
+        chain.add_memory(d.0.as_mut_ptr() as usize, 0x2000).unwrap();
+        assert_eq!(chain.first().total_bytes(), 0x4000);
+        assert_eq!(chain.total(), 0x8000);
+        assert!(!chain.owns(NonNull::dangling()));
+    }
+}
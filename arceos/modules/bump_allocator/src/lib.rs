@@ -1,8 +1,65 @@
 #![no_std]
 
+use core::fmt;
 use core::ptr::NonNull;
 
-use allocator::{AllocError, BaseAllocator, ByteAllocator, PageAllocator};
+use allocator::{AllocError, AllocResult, BaseAllocator, ByteAllocator, PageAllocator};
+
+mod arena;
+mod chain;
+mod slab;
+mod tracking;
+
+pub use arena::Arena;
+pub use chain::{AddMemoryTo, ChainAllocator, OwnedRegion, Pool};
+pub use slab::SlabPool;
+pub use tracking::{LiveAlloc, TrackError, TrackingAllocator, RECENT_FREES};
+
+/// Usage statistics common to all allocators, so that memory reports do not
+/// depend on which allocator is in use.
+pub trait AllocStats {
+    /// Total bytes managed by the allocator.
+    fn total(&self) -> usize;
+    /// Bytes currently allocated.
+    fn used(&self) -> usize;
+    /// Highest value of [`used`](AllocStats::used) so far.
+    fn peak(&self) -> usize;
+    /// Number of successful allocations so far.
+    fn alloc_count(&self) -> usize;
+    /// Number of failed allocations so far.
+    fn fail_count(&self) -> usize;
+
+    /// Takes a snapshot of all statistics.
+    fn report(&self) -> StatsReport {
+        StatsReport {
+            total: self.total(),
+            used: self.used(),
+            peak: self.peak(),
+            alloc_count: self.alloc_count(),
+            fail_count: self.fail_count(),
+        }
+    }
+}
+
+/// A snapshot of [`AllocStats`], printable without allocating.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StatsReport {
+    pub total: usize,
+    pub used: usize,
+    pub peak: usize,
+    pub alloc_count: usize,
+    pub fail_count: usize,
+}
+
+impl fmt::Display for StatsReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "used {}/{} bytes (peak {}), {} allocations, {} failed",
+            self.used, self.total, self.peak, self.alloc_count, self.fail_count
+        )
+    }
+}
 
 /// Early memory allocator
 /// Use it before formal bytes-allocator and pages-allocator can work!
@@ -24,6 +81,10 @@ pub struct EarlyAllocator<const PAGE_SIZE: usize> {
     b_pos: usize,
     p_pos: usize,
     b_count: usize,
+    // Statistics of both areas, see `AllocStats`.
+    peak: usize,
+    alloc_count: usize,
+    fail_count: usize,
 }
 
 impl<const PAGE_SIZE: usize> EarlyAllocator<PAGE_SIZE> {
@@ -34,7 +95,52 @@ impl<const PAGE_SIZE: usize> EarlyAllocator<PAGE_SIZE> {
             b_pos: 0,
             p_pos: 0,
             b_count: 0,
+            peak: 0,
+            alloc_count: 0,
+            fail_count: 0,
+        }
+    }
+
+    /// Updates the statistics after an allocation.
+    fn record<T>(&mut self, result: AllocResult<T>) -> AllocResult<T> {
+        if result.is_ok() {
+            self.alloc_count += 1;
+            self.peak = self.peak.max(self.used());
+        } else {
+            self.fail_count += 1;
+        }
+        result
+    }
+
+    fn alloc_bytes(&mut self, layout: core::alloc::Layout) -> AllocResult<NonNull<u8>> {
+        let align = layout.align();
+        let size = layout.size();
+        let alloc_start = (self.b_pos + align - 1) & !(align - 1);
+        let alloc_end = alloc_start.checked_add(size).ok_or(AllocError::NoMemory)?;
+
+        if alloc_end > self.p_pos {
+            return Err(AllocError::MemoryOverlap);
         }
+
+        self.b_pos = alloc_end;
+        self.b_count += 1;
+        Ok(unsafe { NonNull::new_unchecked(alloc_start as *mut u8) })
+    }
+
+    fn alloc_pages_back(&mut self, num_pages: usize, align_pow2: usize) -> AllocResult<usize> {
+        let align = 1 << align_pow2;
+        let size = num_pages * PAGE_SIZE;
+
+        let alloc_end = self.p_pos;
+        let mut alloc_start = alloc_end.checked_sub(size).ok_or(AllocError::NoMemory)?;
+        alloc_start &= !(align - 1);
+
+        if alloc_start < self.b_pos {
+            return Err(AllocError::MemoryOverlap);
+        }
+
+        self.p_pos = alloc_start;
+        Ok(alloc_start)
     }
 }
 
@@ -53,18 +159,8 @@ impl<const PAGE_SIZE: usize> BaseAllocator for EarlyAllocator<PAGE_SIZE> {
 
 impl<const PAGE_SIZE: usize> ByteAllocator for EarlyAllocator<PAGE_SIZE> {
     fn alloc(&mut self, layout: core::alloc::Layout) -> allocator::AllocResult<core::ptr::NonNull<u8>> {
-        let align = layout.align();
-        let size = layout.size();
-        let alloc_start = (self.b_pos + align - 1) & !(align - 1);
-        let alloc_end = alloc_start.checked_add(size).ok_or(AllocError::NoMemory)?;
-
-        if alloc_end > self.p_pos {
-            return Err(AllocError::MemoryOverlap);
-        }
-
-        self.b_pos = alloc_end;
-        self.b_count += 1;
-        Ok(unsafe { NonNull::new_unchecked(alloc_start as *mut u8) })
+        let result = self.alloc_bytes(layout);
+        self.record(result)
     }
 
     fn dealloc(&mut self, _pos: core::ptr::NonNull<u8>, _layout: core::alloc::Layout) {
@@ -95,19 +191,8 @@ impl<const PAGE_SIZE: usize> PageAllocator for EarlyAllocator<PAGE_SIZE> {
     const PAGE_SIZE: usize = PAGE_SIZE;
 
     fn alloc_pages(&mut self, num_pages: usize, align_pow2: usize) -> allocator::AllocResult<usize> {
-        let align = 1 << align_pow2;
-        let size = num_pages * PAGE_SIZE;
-
-        let alloc_end = self.p_pos;
-        let mut alloc_start = alloc_end.checked_sub(size).ok_or(AllocError::NoMemory)?;
-        alloc_start &= !(align - 1);
-
-        if alloc_start < self.b_pos {
-            return Err(AllocError::MemoryOverlap);
-        }
-
-        self.p_pos = alloc_start;
-        Ok(alloc_start)
+        let result = self.alloc_pages_back(num_pages, align_pow2);
+        self.record(result)
     }
 
     fn dealloc_pages(&mut self, _pos: usize, _num_pages: usize) {
@@ -126,3 +211,108 @@ impl<const PAGE_SIZE: usize> PageAllocator for EarlyAllocator<PAGE_SIZE> {
         (self.p_pos - self.b_pos) / PAGE_SIZE
     }
 }
+
+impl<const PAGE_SIZE: usize> OwnedRegion for EarlyAllocator<PAGE_SIZE> {
+    fn owns(&self, ptr: NonNull<u8>) -> bool {
+        (self.start..self.end).contains(&(ptr.as_ptr() as usize))
+    }
+}
+
+/// Counts both areas: the bytes area and the pages area.
+impl<const PAGE_SIZE: usize> AllocStats for EarlyAllocator<PAGE_SIZE> {
+    fn total(&self) -> usize {
+        self.end - self.start
+    }
+
+    fn used(&self) -> usize {
+        (self.b_pos - self.start) + (self.end - self.p_pos)
+    }
+
+    fn peak(&self) -> usize {
+        self.peak
+    }
+
+    fn alloc_count(&self) -> usize {
+        self.alloc_count
+    }
+
+    fn fail_count(&self) -> usize {
+        self.fail_count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::alloc::Layout;
+    use core::fmt::Write;
+
+    /// A fixed buffer to format into, proving that `Display` does not need
+    /// an allocator.
+    struct Buf {
+        bytes: [u8; 128],
+        len: usize,
+    }
+
+    impl Write for Buf {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            let end = self.len + s.len();
+            self.bytes
+                .get_mut(self.len..end)
+                .ok_or(fmt::Error)?
+                .copy_from_slice(s.as_bytes());
+            self.len = end;
+            Ok(())
+        }
+    }
+
+    /// Checks the statistics of any byte allocator initialized with at
+    /// least 1 KiB.
+    pub(crate) fn exercise<T: AllocStats + ByteAllocator>(alloc: &mut T) {
+        let layout = Layout::from_size_align(64, 8).unwrap();
+        let before = alloc.report();
+        let a = alloc.alloc(layout).unwrap();
+        let b = alloc.alloc(layout).unwrap();
+        assert_eq!(alloc.alloc_count(), before.alloc_count + 2);
+        assert!(alloc.used() >= before.used + 128);
+        assert!(alloc.peak() >= alloc.used());
+        assert!(alloc.used() <= alloc.total());
+
+        let huge = Layout::from_size_align(alloc.total() + 1, 8).unwrap();
+        assert!(alloc.alloc(huge).is_err());
+        assert_eq!(alloc.fail_count(), before.fail_count + 1);
+        assert_eq!(alloc.alloc_count(), before.alloc_count + 2);
+
+        let peak = alloc.peak();
+        alloc.dealloc(a, layout);
+        alloc.dealloc(b, layout);
+        assert!(alloc.used() < before.used + 128);
+        assert_eq!(alloc.peak(), peak);
+
+        let report = alloc.report();
+        let mut buf = Buf {
+            bytes: [0; 128],
+            len: 0,
+        };
+        write!(buf, "{}", report).unwrap();
+        assert!(buf.bytes[..buf.len].starts_with(b"used "));
+    }
+
+    #[test]
+    fn test_early_allocator_stats() {
+        #[repr(align(4096))]
+        struct Heap([u8; 0x4000]);
+        let mut heap = Heap([0; 0x4000]);
+        let mut alloc = EarlyAllocator::<0x1000>::new();
+        alloc.init(heap.0.as_mut_ptr() as usize, heap.0.len());
+        exercise(&mut alloc);
+
+        // Pages count too, and are never freed.
+        alloc.alloc_pages(1, 12).unwrap();
+        assert_eq!(alloc.used(), 0x1000);
+        assert!(alloc.alloc_pages(4, 12).is_err());
+        let report = alloc.report();
+        assert_eq!((report.alloc_count, report.fail_count), (3, 2));
+        assert_eq!((report.total, report.peak), (0x4000, 0x1000));
+    }
+}
@@ -0,0 +1,263 @@
+use core::alloc::Layout;
+use core::ptr::NonNull;
+
+use allocator::{AllocError, AllocResult, BaseAllocator, ByteAllocator};
+
+use crate::{AllocStats, OwnedRegion};
+
+/// A pool of fixed-size slots, for objects of exactly `OBJ_SIZE` bytes.
+///
+/// The region given to `init` is carved into slots, which are aligned to the
+/// largest power of two dividing `OBJ_SIZE`, up to a page. Freed slots are
+/// threaded into a free list through their first word, and slots never used
+/// yet are handed out in order, so that both allocating and freeing take
+/// constant time and the pool never fragments.
+///
+/// Requests of another size or a larger alignment fail with
+/// [`AllocError::InvalidParam`], without counting as failures: chain the
+/// pool before a general allocator with a [`ChainAllocator`] to serve them
+/// there.
+///
+/// [`ChainAllocator`]: crate::ChainAllocator
+pub struct SlabPool<const OBJ_SIZE: usize> {
+    start: usize,
+    end: usize,
+    /// First slot of the free list, 0 if empty.
+    free: usize,
+    /// First slot never used yet; slots from here to `end` are free.
+    fresh: usize,
+    live: usize,
+    peak: usize,
+    alloc_count: usize,
+    fail_count: usize,
+}
+
+impl<const OBJ_SIZE: usize> SlabPool<OBJ_SIZE> {
+    /// Alignment of every slot.
+    pub const SLOT_ALIGN: usize = {
+        let align = 1 << OBJ_SIZE.trailing_zeros();
+        if align > 0x1000 {
+            0x1000
+        } else {
+            align
+        }
+    };
+
+    /// Creates an empty pool.
+    pub const fn new() -> Self {
+        assert!(
+            OBJ_SIZE >= core::mem::size_of::<usize>()
+                && Self::SLOT_ALIGN >= core::mem::align_of::<usize>(),
+            "slots must be able to hold a free list link"
+        );
+        Self {
+            start: 0,
+            end: 0,
+            free: 0,
+            fresh: 0,
+            live: 0,
+            peak: 0,
+            alloc_count: 0,
+            fail_count: 0,
+        }
+    }
+
+    /// Returns the number of allocated objects.
+    pub fn live_count(&self) -> usize {
+        self.live
+    }
+
+    /// Returns the number of slots.
+    pub fn capacity(&self) -> usize {
+        (self.end - self.start) / OBJ_SIZE
+    }
+
+    fn take_slot(&mut self) -> Option<usize> {
+        if self.free != 0 {
+            let slot = self.free;
+            // SAFETY: Free slots hold the address of the next free slot.
+            self.free = unsafe { *(slot as *const usize) };
+            Some(slot)
+        } else if self.fresh < self.end {
+            let slot = self.fresh;
+            self.fresh += OBJ_SIZE;
+            Some(slot)
+        } else {
+            None
+        }
+    }
+}
+
+impl<const OBJ_SIZE: usize> Default for SlabPool<OBJ_SIZE> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const OBJ_SIZE: usize> OwnedRegion for SlabPool<OBJ_SIZE> {
+    fn owns(&self, ptr: NonNull<u8>) -> bool {
+        (self.start..self.end).contains(&(ptr.as_ptr() as usize))
+    }
+}
+
+impl<const OBJ_SIZE: usize> BaseAllocator for SlabPool<OBJ_SIZE> {
+    fn init(&mut self, start: usize, size: usize) {
+        let end = start + size;
+        let start = start.next_multiple_of(Self::SLOT_ALIGN).min(end);
+        self.start = start;
+        self.end = start + (end - start) / OBJ_SIZE * OBJ_SIZE;
+        self.free = 0;
+        self.fresh = start;
+        self.live = 0;
+    }
+
+    fn add_memory(&mut self, _start: usize, _size: usize) -> AllocResult {
+        Err(AllocError::NoMemory) // unsupported
+    }
+}
+
+impl<const OBJ_SIZE: usize> ByteAllocator for SlabPool<OBJ_SIZE> {
+    fn alloc(&mut self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        if layout.size() != OBJ_SIZE || layout.align() > Self::SLOT_ALIGN {
+            return Err(AllocError::InvalidParam);
+        }
+        let Some(slot) = self.take_slot() else {
+            self.fail_count += 1;
+            return Err(AllocError::NoMemory);
+        };
+        self.live += 1;
+        self.alloc_count += 1;
+        self.peak = self.peak.max(self.used_bytes());
+        Ok(unsafe { NonNull::new_unchecked(slot as *mut u8) })
+    }
+
+    fn dealloc(&mut self, pos: NonNull<u8>, _layout: Layout) {
+        let slot = pos.as_ptr() as usize;
+        debug_assert!(self.owns(pos) && (slot - self.start) % OBJ_SIZE == 0);
+        // SAFETY: The slot is allocated, so it is ours to overwrite.
+        unsafe { *(slot as *mut usize) = self.free };
+        self.free = slot;
+        self.live -= 1;
+    }
+
+    fn total_bytes(&self) -> usize {
+        self.end - self.start
+    }
+
+    fn used_bytes(&self) -> usize {
+        self.live * OBJ_SIZE
+    }
+
+    fn available_bytes(&self) -> usize {
+        self.total_bytes() - self.used_bytes()
+    }
+}
+
+impl<const OBJ_SIZE: usize> AllocStats for SlabPool<OBJ_SIZE> {
+    fn total(&self) -> usize {
+        self.total_bytes()
+    }
+
+    fn used(&self) -> usize {
+        self.used_bytes()
+    }
+
+    fn peak(&self) -> usize {
+        self.peak
+    }
+
+    fn alloc_count(&self) -> usize {
+        self.alloc_count
+    }
+
+    fn fail_count(&self) -> usize {
+        self.fail_count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChainAllocator, Pool};
+    use allocator::TlsfByteAllocator;
+
+    #[repr(align(4096))]
+    struct Region([u8; 0x4000]);
+
+    fn xorshift(state: &mut u64) -> u64 {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        *state
+    }
+
+    #[test]
+    fn test_hammer() {
+        const SIZE: usize = 0x180;
+        let mut region = Region([0; 0x4000]);
+        let mut slab = SlabPool::<SIZE>::new();
+        // Start off the slot alignment, which init makes up for.
+        slab.init(region.0.as_mut_ptr() as usize + 8, region.0.len() - 8);
+        assert_eq!(SlabPool::<SIZE>::SLOT_ALIGN, 0x80);
+        let capacity = slab.capacity();
+        assert_eq!(capacity, (0x4000 - 0x80) / SIZE);
+        let layout = Layout::from_size_align(SIZE, 16).unwrap();
+
+        let mut slots = [None; 64];
+        let mut state = 0x2545_f491_4f6c_dd1d;
+        for _ in 0..10_000 {
+            let slot = &mut slots[xorshift(&mut state) as usize % capacity];
+            match slot.take() {
+                Some(ptr) => slab.dealloc(ptr, layout),
+                None => {
+                    let ptr = slab.alloc(layout).unwrap();
+                    assert!(slab.owns(ptr));
+                    assert_eq!(ptr.as_ptr() as usize % SlabPool::<SIZE>::SLOT_ALIGN, 0);
+                    // The object is ours to write.
+                    unsafe { ptr.as_ptr().write_bytes(0xa5, SIZE) };
+                    *slot = Some(ptr);
+                }
+            }
+            let live = slots.iter().flatten().count();
+            assert_eq!(slab.live_count(), live);
+            assert_eq!(slab.used(), live * SIZE);
+        }
+
+        // Whatever the history, every slot can be allocated.
+        for slot in slots[..capacity].iter_mut().filter(|slot| slot.is_none()) {
+            *slot = Some(slab.alloc(layout).unwrap());
+        }
+        assert_eq!(slab.available_bytes(), 0);
+        assert!(slab.alloc(layout).is_err());
+        assert_eq!(slab.fail_count(), 1);
+        assert_eq!(slab.peak(), capacity * SIZE);
+    }
+
+    #[test]
+    fn test_fallback() {
+        let (mut a, mut b) = (Region([0; 0x4000]), Region([0; 0x4000]));
+        let mut slab = SlabPool::<0x20>::new();
+        slab.init(a.0.as_mut_ptr() as usize, 0x100);
+        let mut tlsf: Pool<_> = Pool::new(TlsfByteAllocator::new());
+        tlsf.init(b.0.as_mut_ptr() as usize, b.0.len());
+        let mut chain = ChainAllocator::new(slab, tlsf);
+
+        let header = Layout::from_size_align(0x20, 8).unwrap();
+        let block = Layout::from_size_align(0x40, 8).unwrap();
+        let overaligned = Layout::from_size_align(0x20, 64).unwrap();
+        let ptrs = [(); 8].map(|_| chain.alloc(header).unwrap());
+        assert!(ptrs.iter().all(|&ptr| chain.first().owns(ptr)));
+        for layout in [block, overaligned, header] {
+            let ptr = chain.alloc(layout).unwrap();
+            assert!(chain.second().owns(ptr));
+            chain.dealloc(ptr, layout);
+        }
+        // Only the full slab counts as a failure.
+        assert_eq!(chain.first().fail_count(), 1);
+        assert_eq!(chain.first().used(), 8 * 0x20);
+
+        chain.dealloc(ptrs[3], header);
+        assert_eq!(chain.alloc(header).unwrap(), ptrs[3]);
+        assert_eq!(chain.second().used_bytes(), 0);
+    }
+}
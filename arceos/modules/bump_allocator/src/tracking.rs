@@ -0,0 +1,337 @@
+use core::alloc::Layout;
+use core::fmt;
+use core::ptr::NonNull;
+
+use allocator::{AllocResult, BaseAllocator, ByteAllocator};
+
+use crate::OwnedRegion;
+
+/// A live allocation recorded by a [`TrackingAllocator`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LiveAlloc {
+    pub addr: usize,
+    pub size: usize,
+    pub align: usize,
+    /// Position of the allocation among all allocations, from 0.
+    pub seq: u64,
+}
+
+/// A misuse detected by a [`TrackingAllocator`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrackError {
+    /// Freeing an address that is not allocated, e.g. for the second time.
+    /// The call is not forwarded.
+    DoubleFree { addr: usize },
+    /// Freeing with another layout than the allocation's. The call is
+    /// forwarded with the recorded layout.
+    LayoutMismatch {
+        addr: usize,
+        allocated: Layout,
+        freed: Layout,
+    },
+}
+
+/// Number of freed addresses a [`TrackingAllocator`] remembers.
+pub const RECENT_FREES: usize = 8;
+
+/// A byte allocator wrapper recording every live allocation, to find leaks,
+/// double frees and mismatched layouts.
+///
+/// The records are kept in `table`, memory separate from the tracked
+/// allocator, so that tracking never allocates. Allocations beyond the size
+/// of the table are served but not tracked, see [`untracked`]; frees of
+/// unknown addresses are then forwarded, since they may be legitimate.
+///
+/// The last [`RECENT_FREES`] tracked frees are remembered as well, so that
+/// freeing one of them again is reported even while some allocations are
+/// untracked. Older double frees, and double frees of untracked allocations,
+/// go unnoticed in that case.
+///
+/// [`untracked`]: TrackingAllocator::untracked
+pub struct TrackingAllocator<'a, A> {
+    inner: A,
+    table: &'a mut [Option<LiveAlloc>],
+    /// Addresses of the last tracked frees, overwritten in turn.
+    recent_frees: [Option<usize>; RECENT_FREES],
+    next_free: usize,
+    next_seq: u64,
+    untracked: usize,
+    errors: usize,
+    last_error: Option<TrackError>,
+}
+
+impl<'a, A> TrackingAllocator<'a, A> {
+    /// Wraps `inner`, recording its allocations in `table`, which must be
+    /// empty.
+    pub fn new(inner: A, table: &'a mut [Option<LiveAlloc>]) -> Self {
+        debug_assert!(table.iter().all(Option::is_none));
+        Self {
+            inner,
+            table,
+            recent_frees: [None; RECENT_FREES],
+            next_free: 0,
+            next_seq: 0,
+            untracked: 0,
+            errors: 0,
+            last_error: None,
+        }
+    }
+
+    /// Returns the wrapped allocator.
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// An iterator over the live allocations, in no particular order.
+    pub fn live(&self) -> impl Iterator<Item = &LiveAlloc> {
+        self.table.iter().flatten()
+    }
+
+    /// Returns the number of allocations that did not fit in the table.
+    pub fn untracked(&self) -> usize {
+        self.untracked
+    }
+
+    /// Returns the number of misuses detected so far.
+    pub fn errors(&self) -> usize {
+        self.errors
+    }
+
+    /// Returns the last misuse detected.
+    pub fn last_error(&self) -> Option<TrackError> {
+        self.last_error
+    }
+
+    /// Panics, listing every live allocation, if there is any.
+    pub fn assert_no_leaks(&self) {
+        let leaks = self.live().count();
+        if leaks > 0 {
+            panic!("{} allocations leaked:{}", leaks, Leaks(self.table));
+        }
+    }
+
+    fn report(&mut self, error: TrackError) {
+        self.errors += 1;
+        self.last_error = Some(error);
+    }
+}
+
+/// Lists live allocations, one per line, without allocating.
+struct Leaks<'a>(&'a [Option<LiveAlloc>]);
+
+impl fmt::Display for Leaks<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for a in self.0.iter().flatten() {
+            write!(
+                f,
+                "\n  #{} at {:#x}: {} bytes, align {}",
+                a.seq, a.addr, a.size, a.align
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl<A: OwnedRegion> OwnedRegion for TrackingAllocator<'_, A> {
+    fn owns(&self, ptr: NonNull<u8>) -> bool {
+        self.inner.owns(ptr)
+    }
+}
+
+impl<A: BaseAllocator> BaseAllocator for TrackingAllocator<'_, A> {
+    fn init(&mut self, start: usize, size: usize) {
+        self.inner.init(start, size)
+    }
+
+    fn add_memory(&mut self, start: usize, size: usize) -> AllocResult {
+        self.inner.add_memory(start, size)
+    }
+}
+
+impl<A: ByteAllocator> ByteAllocator for TrackingAllocator<'_, A> {
+    fn alloc(&mut self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        let ptr = self.inner.alloc(layout)?;
+        let record = LiveAlloc {
+            addr: ptr.as_ptr() as usize,
+            size: layout.size(),
+            align: layout.align(),
+            seq: self.next_seq,
+        };
+        self.next_seq += 1;
+        // The address is live again, freeing it is fine from now on.
+        for freed in &mut self.recent_frees {
+            if *freed == Some(record.addr) {
+                *freed = None;
+            }
+        }
+        match self.table.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) => *slot = Some(record),
+            None => self.untracked += 1,
+        }
+        Ok(ptr)
+    }
+
+    fn dealloc(&mut self, pos: NonNull<u8>, layout: Layout) {
+        let addr = pos.as_ptr() as usize;
+        let slot = self
+            .table
+            .iter_mut()
+            .find(|slot| slot.map_or(false, |a| a.addr == addr));
+        let Some(slot) = slot else {
+            if self.untracked == 0 || self.recent_frees.contains(&Some(addr)) {
+                self.report(TrackError::DoubleFree { addr });
+                return;
+            }
+            self.untracked -= 1;
+            return self.inner.dealloc(pos, layout);
+        };
+        let record = slot.take().unwrap();
+        self.recent_frees[self.next_free] = Some(addr);
+        self.next_free = (self.next_free + 1) % RECENT_FREES;
+        // Both were valid layouts when allocating.
+        let allocated = Layout::from_size_align(record.size, record.align).unwrap();
+        if allocated != layout {
+            self.report(TrackError::LayoutMismatch {
+                addr,
+                allocated,
+                freed: layout,
+            });
+        }
+        self.inner.dealloc(pos, allocated)
+    }
+
+    fn total_bytes(&self) -> usize {
+        self.inner.total_bytes()
+    }
+
+    fn used_bytes(&self) -> usize {
+        self.inner.used_bytes()
+    }
+
+    fn available_bytes(&self) -> usize {
+        self.inner.available_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::Pool;
+    use allocator::TlsfByteAllocator;
+    use std::string::String;
+
+    #[repr(align(4096))]
+    struct Region([u8; 0x2000]);
+
+    fn tlsf(region: &mut Region) -> Pool<TlsfByteAllocator> {
+        let mut pool = Pool::new(TlsfByteAllocator::new());
+        pool.init(region.0.as_mut_ptr() as usize, region.0.len());
+        pool
+    }
+
+    #[test]
+    fn test_leaks() {
+        let mut region = Region([0; 0x2000]);
+        let mut table = [None; 8];
+        let mut alloc = TrackingAllocator::new(tlsf(&mut region), &mut table);
+        let small = Layout::from_size_align(24, 8).unwrap();
+        let large = Layout::from_size_align(0x100, 64).unwrap();
+
+        let freed = alloc.alloc(small).unwrap();
+        let leaked_a = alloc.alloc(large).unwrap();
+        let leaked_b = alloc.alloc(small).unwrap();
+        alloc.dealloc(freed, small);
+        assert_eq!(alloc.live().count(), 2);
+        assert!(alloc
+            .live()
+            .any(|a| a.addr == leaked_a.as_ptr() as usize
+                && (a.size, a.align, a.seq) == (0x100, 64, 1)));
+        assert!(alloc
+            .live()
+            .any(|a| a.addr == leaked_b.as_ptr() as usize && a.seq == 2));
+
+        let report = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            alloc.assert_no_leaks();
+        }))
+        .unwrap_err();
+        let report = report.downcast::<String>().unwrap();
+        assert!(report.starts_with("2 allocations leaked:"));
+        for ptr in [leaked_a, leaked_b] {
+            assert!(report.contains(&std::format!("at {:#x}:", ptr.as_ptr() as usize)));
+        }
+        assert!(!report.contains(&std::format!("at {:#x}:", freed.as_ptr() as usize)));
+
+        alloc.dealloc(leaked_a, large);
+        alloc.dealloc(leaked_b, small);
+        alloc.assert_no_leaks();
+        assert_eq!((alloc.errors(), alloc.inner().used_bytes()), (0, 0));
+    }
+
+    #[test]
+    fn test_misuse() {
+        let mut region = Region([0; 0x2000]);
+        let mut table = [None; 2];
+        let mut alloc = TrackingAllocator::new(tlsf(&mut region), &mut table);
+        let layout = Layout::from_size_align(32, 8).unwrap();
+
+        let ptr = alloc.alloc(layout).unwrap();
+        alloc.dealloc(ptr, layout);
+        alloc.dealloc(ptr, layout);
+        let addr = ptr.as_ptr() as usize;
+        assert_eq!(alloc.last_error(), Some(TrackError::DoubleFree { addr }));
+
+        // The inner allocator is freed with the right layout anyway.
+        let ptr = alloc.alloc(layout).unwrap();
+        let wrong = Layout::from_size_align(16, 8).unwrap();
+        alloc.dealloc(ptr, wrong);
+        assert_eq!(
+            alloc.last_error(),
+            Some(TrackError::LayoutMismatch {
+                addr: ptr.as_ptr() as usize,
+                allocated: layout,
+                freed: wrong,
+            })
+        );
+        assert_eq!(alloc.errors(), 2);
+        assert_eq!(alloc.inner().used_bytes(), 0);
+
+        // Past the table, allocations are served but not tracked.
+        let ptrs = [(); 3].map(|_| alloc.alloc(layout).unwrap());
+        assert_eq!((alloc.live().count(), alloc.untracked()), (2, 1));
+        for ptr in ptrs {
+            alloc.dealloc(ptr, layout);
+        }
+        assert_eq!((alloc.errors(), alloc.untracked()), (2, 0));
+        alloc.assert_no_leaks();
+    }
+
+    #[test]
+    fn test_double_free_while_untracked() {
+        let mut region = Region([0; 0x2000]);
+        let mut table = [None; 2];
+        let mut alloc = TrackingAllocator::new(tlsf(&mut region), &mut table);
+        let layout = Layout::from_size_align(32, 8).unwrap();
+
+        let ptrs = [(); 3].map(|_| alloc.alloc(layout).unwrap());
+        assert_eq!(alloc.untracked(), 1);
+        alloc.dealloc(ptrs[0], layout);
+        let used = alloc.inner().used_bytes();
+        alloc.dealloc(ptrs[0], layout);
+        let addr = ptrs[0].as_ptr() as usize;
+        assert_eq!(alloc.last_error(), Some(TrackError::DoubleFree { addr }));
+        assert_eq!(alloc.inner().used_bytes(), used);
+
+        // The untracked allocation is still freed normally, and reusing the
+        // freed address makes it fine to free again.
+        alloc.dealloc(ptrs[2], layout);
+        let again = alloc.alloc(layout).unwrap();
+        assert_eq!(again, ptrs[0]);
+        alloc.dealloc(again, layout);
+        alloc.dealloc(ptrs[1], layout);
+        assert_eq!((alloc.errors(), alloc.untracked()), (1, 0));
+        assert_eq!(alloc.inner().used_bytes(), 0);
+        alloc.assert_no_leaks();
+    }
+}
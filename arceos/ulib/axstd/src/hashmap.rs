@@ -1,11 +1,72 @@
 extern crate alloc;
 
-use alloc::vec::Vec;
+use alloc::collections::TryReserveError;
+use alloc::vec::{self, Vec};
+#[cfg(not(feature = "deterministic-hash"))]
 use arceos_api::modules::axhal;
-use core::hash::{Hash, Hasher};
+#[cfg(debug_assertions)]
+use arceos_api::modules::axlog;
+use core::borrow::Borrow;
+use core::fmt;
+use core::hash::{BuildHasher, Hash, Hasher};
+use core::iter::{Chain, FusedIterator};
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut, Index};
+use core::{array, slice};
 
+#[derive(Default)]
 pub struct SimpleHasher(u128);
 
+impl SimpleHasher {
+    /// Creates a hasher with a zero seed, hashing the same on every run.
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    /// Mixes a whole integer into the state with a single multiplication,
+    /// instead of one round per byte.
+    ///
+    /// The odd constant spreads every input bit over the upper half of the
+    /// product, which [`finish128`] mixes back into the lower half. The
+    /// offset keeps zero words from leaving a zero state unchanged.
+    ///
+    /// [`finish128`]: SimpleHasher::finish128
+    #[inline]
+    fn write_word(&mut self, word: u128) {
+        const OFFSET: u128 = 0x9e37_79b9_7f4a_7c15;
+        const MULTIPLIER: u128 = 0x2d35_8dcc_aa6c_78a5_8bb8_4b93_962e_acc9;
+        self.0 = (self.0 ^ word)
+            .wrapping_add(OFFSET)
+            .wrapping_mul(MULTIPLIER);
+    }
+
+    /// Returns the full 128-bit digest of the data written so far.
+    ///
+    /// Both halves of the state go through the 64-bit finalizer of
+    /// MurmurHash3, the upper one first so that it is mixed into the lower
+    /// one. Every input bit thus reaches every output bit, even for inputs
+    /// that differ only in their last byte. [`finish`] returns the lower
+    /// half.
+    ///
+    /// [`finish`]: Hasher::finish
+    pub fn finish128(&self) -> u128 {
+        let hi = fmix64((self.0 >> 64) as u64);
+        let lo = fmix64(self.0 as u64 ^ hi);
+        (hi as u128) << 64 | lo as u128
+    }
+}
+
+/// The finalizer of MurmurHash3: a bijection of 64-bit words where every
+/// input bit flips each output bit with a probability close to 1/2.
+#[inline]
+fn fmix64(mut x: u64) -> u64 {
+    x ^= x >> 33;
+    x = x.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    x ^= x >> 33;
+    x = x.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    x ^ x >> 33
+}
+
 impl Hasher for SimpleHasher {
     fn write(&mut self, bytes: &[u8]) {
         for &b in bytes {
@@ -14,80 +75,5198 @@ impl Hasher for SimpleHasher {
         }
     }
 
+    fn write_u8(&mut self, i: u8) {
+        self.write_word(i as u128);
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.write_word(i as u128);
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write_word(i as u128);
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write_word(i as u128);
+    }
+
+    fn write_u128(&mut self, i: u128) {
+        self.write_word(i);
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_word(i as u128);
+    }
+
+    fn write_length_prefix(&mut self, len: usize) {
+        self.write_word(len as u128);
+    }
+
     fn finish(&self) -> u64 {
-        (self.0 as u64) ^ ((self.0 >> 64) as u64)
+        self.finish128() as u64
     }
 }
 
-pub struct HashMap<K, V> {
-    items: Vec<Vec<(K, V)>>,
-    hash_salt: u128,
-    size: usize,
+/// The seed of every hash state created by `new()` with the
+/// `deterministic-hash` feature.
+///
+/// With the feature enabled, every map hashes and iterates the same way on
+/// every run, which makes tests and debugging sessions reproducible. The
+/// price is that the seed is public: anyone can compute which keys collide and
+/// slow the maps down to linear scans. Never enable it in a kernel exposed to
+/// untrusted input.
+pub const DETERMINISTIC_SEED: u128 = 0x243f_6a88_85a3_08d3_1319_8a2e_0370_7344;
+
+/// Returns the seed of a new hash state.
+#[cfg(not(feature = "deterministic-hash"))]
+fn random_seed() -> u128 {
+    axhal::misc::random()
+}
+
+/// Returns the seed of a new hash state.
+#[cfg(feature = "deterministic-hash")]
+fn random_seed() -> u128 {
+    DETERMINISTIC_SEED
+}
+
+/// A [`BuildHasher`] creating [`SimpleHasher`]s that all start from the same
+/// seed.
+///
+/// This is the default hash state of [`HashMap`], unless the `secure-hash`
+/// feature selects [`SipBuildHasher`] instead. It can also be used on its
+/// own to hash keys consistently outside of a map.
+#[derive(Clone, Debug)]
+pub struct SimpleBuildHasher {
+    seed: u128,
 }
 
-impl<K: Eq + Hash, V> HashMap<K, V> {
+impl SimpleBuildHasher {
+    /// Creates a new builder with a random seed obtained from
+    /// [`axhal::misc::random`], or with [`DETERMINISTIC_SEED`] if the
+    /// `deterministic-hash` feature is enabled.
+    ///
+    /// [`axhal::misc::random`]: arceos_api::modules::axhal::misc::random
     pub fn new() -> Self {
-        Self::with_capacity(16)
+        Self::with_seed(random_seed())
     }
 
-    pub fn with_capacity(cap: usize) -> Self {
-        let mut items = Vec::with_capacity(cap);
-        for _ in 0..cap {
-            items.push(Vec::new());
+    /// Creates a new builder with the given seed. Builders with equal seeds
+    /// produce equal hashes.
+    pub const fn with_seed(seed: u128) -> Self {
+        Self { seed }
+    }
+
+    /// Returns the seed of this builder.
+    pub const fn seed(&self) -> u128 {
+        self.seed
+    }
+}
+
+impl Default for SimpleBuildHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BuildHasher for SimpleBuildHasher {
+    type Hasher = SimpleHasher;
+
+    fn build_hasher(&self) -> SimpleHasher {
+        SimpleHasher(self.seed)
+    }
+}
+
+/// The SipHash-1-3 hash function: a keyed hash whose outputs cannot be
+/// predicted without the 128-bit key, so that keys cannot be crafted to
+/// collide.
+///
+/// It is several times slower than [`SimpleHasher`] on short keys. Prefer it
+/// for maps keyed by data an attacker controls, such as ports or paths.
+#[derive(Clone, Debug)]
+pub struct SipHasher13(Sip<1, 3>);
+
+impl SipHasher13 {
+    /// Creates a hasher keyed with zeros, hashing the same on every run.
+    pub const fn new() -> Self {
+        Self::new_with_keys(0, 0)
+    }
+
+    /// Creates a hasher keyed with `k0` and `k1`.
+    pub const fn new_with_keys(k0: u64, k1: u64) -> Self {
+        Self(Sip::new(k0, k1))
+    }
+}
+
+impl Default for SipHasher13 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Hasher for SipHasher13 {
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.write(bytes)
+    }
+
+    fn finish(&self) -> u64 {
+        self.0.finish()
+    }
+}
+
+/// SipHash with `C` compression rounds and `D` finalization rounds, as
+/// specified in "SipHash: a fast short-input PRF" by Aumasson and Bernstein.
+#[derive(Clone, Debug)]
+struct Sip<const C: usize, const D: usize> {
+    v: [u64; 4],
+    /// Input bytes not compressed yet, little-endian in the low `ntail`
+    /// bytes.
+    tail: u64,
+    ntail: usize,
+    length: usize,
+}
+
+impl<const C: usize, const D: usize> Sip<C, D> {
+    const fn new(k0: u64, k1: u64) -> Self {
+        Self {
+            v: [
+                k0 ^ 0x736f_6d65_7073_6575,
+                k1 ^ 0x646f_7261_6e64_6f6d,
+                k0 ^ 0x6c79_6765_6e65_7261,
+                k1 ^ 0x7465_6462_7974_6573,
+            ],
+            tail: 0,
+            ntail: 0,
+            length: 0,
         }
+    }
 
-        HashMap {
-            items,
-            hash_salt: axhal::misc::random(),
+    fn round(v: &mut [u64; 4]) {
+        v[0] = v[0].wrapping_add(v[1]);
+        v[1] = v[1].rotate_left(13) ^ v[0];
+        v[0] = v[0].rotate_left(32);
+        v[2] = v[2].wrapping_add(v[3]);
+        v[3] = v[3].rotate_left(16) ^ v[2];
+        v[0] = v[0].wrapping_add(v[3]);
+        v[3] = v[3].rotate_left(21) ^ v[0];
+        v[2] = v[2].wrapping_add(v[1]);
+        v[1] = v[1].rotate_left(17) ^ v[2];
+        v[2] = v[2].rotate_left(32);
+    }
+
+    fn compress(v: &mut [u64; 4], m: u64) {
+        v[3] ^= m;
+        for _ in 0..C {
+            Self::round(v);
+        }
+        v[0] ^= m;
+    }
+}
+
+impl<const C: usize, const D: usize> Hasher for Sip<C, D> {
+    fn write(&mut self, mut bytes: &[u8]) {
+        self.length = self.length.wrapping_add(bytes.len());
+        if self.ntail > 0 {
+            let fill = (8 - self.ntail).min(bytes.len());
+            for (i, &b) in bytes[..fill].iter().enumerate() {
+                self.tail |= (b as u64) << (8 * (self.ntail + i));
+            }
+            self.ntail += fill;
+            bytes = &bytes[fill..];
+            if self.ntail < 8 {
+                return;
+            }
+            Self::compress(&mut self.v, self.tail);
+            self.tail = 0;
+            self.ntail = 0;
+        }
+        let mut words = bytes.chunks_exact(8);
+        for word in &mut words {
+            Self::compress(&mut self.v, u64::from_le_bytes(word.try_into().unwrap()));
+        }
+        for (i, &b) in words.remainder().iter().enumerate() {
+            self.tail |= (b as u64) << (8 * i);
+        }
+        self.ntail = words.remainder().len();
+    }
+
+    fn finish(&self) -> u64 {
+        let mut v = self.v;
+        Self::compress(&mut v, (self.length as u64) << 56 | self.tail);
+        v[2] ^= 0xff;
+        for _ in 0..D {
+            Self::round(&mut v);
+        }
+        v[0] ^ v[1] ^ v[2] ^ v[3]
+    }
+}
+
+/// A [`BuildHasher`] creating [`SipHasher13`]s that all share the same key.
+///
+/// This is the default hash state of [`HashMap`] with the `secure-hash`
+/// feature.
+#[derive(Clone, Debug)]
+pub struct SipBuildHasher {
+    k0: u64,
+    k1: u64,
+}
+
+impl SipBuildHasher {
+    /// Creates a new builder with a random key obtained from
+    /// [`axhal::misc::random`], or with [`DETERMINISTIC_SEED`] if the
+    /// `deterministic-hash` feature is enabled.
+    ///
+    /// [`axhal::misc::random`]: arceos_api::modules::axhal::misc::random
+    pub fn new() -> Self {
+        Self::with_seed(random_seed())
+    }
+
+    /// Creates a new builder keyed with `seed`. Builders with equal seeds
+    /// produce equal hashes.
+    pub const fn with_seed(seed: u128) -> Self {
+        Self {
+            k0: seed as u64,
+            k1: (seed >> 64) as u64,
+        }
+    }
+
+    /// Returns the seed this builder was keyed with.
+    pub const fn seed(&self) -> u128 {
+        (self.k1 as u128) << 64 | self.k0 as u128
+    }
+}
+
+impl Default for SipBuildHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BuildHasher for SipBuildHasher {
+    type Hasher = SipHasher13;
+
+    fn build_hasher(&self) -> SipHasher13 {
+        SipHasher13::new_with_keys(self.k0, self.k1)
+    }
+}
+
+/// The default hash state of [`HashMap`], seeded randomly.
+#[cfg(not(feature = "secure-hash"))]
+pub type RandomSeededState = SimpleBuildHasher;
+
+/// The default hash state of [`HashMap`], seeded randomly.
+#[cfg(feature = "secure-hash")]
+pub type RandomSeededState = SipBuildHasher;
+
+/// The default hash state of [`HashMap`], under its name in `std`.
+pub type RandomState = RandomSeededState;
+
+/// The hasher created by [`RandomState`], under its name in `std`.
+pub type DefaultHasher = <RandomState as BuildHasher>::Hasher;
+
+/// A slot of the open-addressing table.
+#[derive(Clone)]
+enum Slot<K, V> {
+    /// Never used since the last rehash. Terminates probe sequences.
+    Empty,
+    /// Removed entry (tombstone). Probe sequences continue past it.
+    Deleted,
+    Full(Bucket<K, V>),
+}
+
+/// An entry of the table, stored together with the hash of its key.
+#[derive(Clone)]
+struct Bucket<K, V> {
+    hash: u64,
+    key: K,
+    value: V,
+}
+
+/// The slots of a [`RawTable`]: either `INLINE` slots stored in place, or
+/// a heap allocation.
+///
+/// With `INLINE` 0 the inline variant is never built, and the enum takes
+/// no more room than the `Vec`, whose pointer niche holds the tag.
+#[derive(Clone)]
+enum Slots<K, V, const INLINE: usize> {
+    Inline([Slot<K, V>; INLINE]),
+    Heap(Vec<Slot<K, V>>),
+}
+
+impl<K, V, const INLINE: usize> Slots<K, V, INLINE> {
+    /// Returns the number of slots allocated on the heap.
+    fn heap_capacity(&self) -> usize {
+        match self {
+            Slots::Inline(_) => 0,
+            Slots::Heap(slots) => slots.capacity(),
+        }
+    }
+}
+
+impl<K, V, const INLINE: usize> Deref for Slots<K, V, INLINE> {
+    type Target = [Slot<K, V>];
+
+    fn deref(&self) -> &[Slot<K, V>] {
+        match self {
+            Slots::Inline(slots) => slots,
+            Slots::Heap(slots) => slots,
+        }
+    }
+}
+
+impl<K, V, const INLINE: usize> DerefMut for Slots<K, V, INLINE> {
+    fn deref_mut(&mut self) -> &mut [Slot<K, V>] {
+        match self {
+            Slots::Inline(slots) => slots,
+            Slots::Heap(slots) => slots,
+        }
+    }
+}
+
+impl<K, V, const INLINE: usize> IntoIterator for Slots<K, V, INLINE> {
+    type Item = Slot<K, V>;
+    type IntoIter = OwnedSlots<K, V, INLINE>;
+
+    fn into_iter(self) -> OwnedSlots<K, V, INLINE> {
+        match self {
+            Slots::Inline(slots) => OwnedSlots::Inline(slots.into_iter()),
+            Slots::Heap(slots) => OwnedSlots::Heap(slots.into_iter()),
+        }
+    }
+}
+
+/// An owning iterator over [`Slots`].
+enum OwnedSlots<K, V, const INLINE: usize> {
+    Inline(array::IntoIter<Slot<K, V>, INLINE>),
+    Heap(vec::IntoIter<Slot<K, V>>),
+}
+
+impl<K, V, const INLINE: usize> Iterator for OwnedSlots<K, V, INLINE> {
+    type Item = Slot<K, V>;
+
+    fn next(&mut self) -> Option<Slot<K, V>> {
+        match self {
+            OwnedSlots::Inline(slots) => slots.next(),
+            OwnedSlots::Heap(slots) => slots.next(),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            OwnedSlots::Inline(slots) => slots.size_hint(),
+            OwnedSlots::Heap(slots) => slots.size_hint(),
+        }
+    }
+}
+
+/// The hash-agnostic storage of [`HashMap`]: a flat table of slots probed
+/// linearly, plus the bookkeeping of live entries and tombstones.
+///
+/// The number of slots is always zero or a power of two, so that a hash is
+/// reduced to a slot index with a mask rather than a division. Tables of at
+/// most `INLINE` slots keep them in place instead of allocating.
+///
+/// Every table mixes its own salt into the hashes before masking, so that
+/// keys colliding in one table are spread apart in the next one. The cached
+/// hashes themselves do not depend on the salt and stay valid.
+#[derive(Clone)]
+pub(crate) struct RawTable<K, V, const INLINE: usize = 0> {
+    slots: Slots<K, V, INLINE>,
+    /// `slots.len() - 1`, or 0 for the empty table.
+    mask: usize,
+    size: usize,
+    deleted: usize,
+    salt: u64,
+}
+
+impl<K, V, const INLINE: usize> RawTable<K, V, INLINE> {
+    /// Returns a table of `len` empty slots, or of the `INLINE` inline slots
+    /// if they are enough.
+    pub(crate) fn with_slots(len: usize, salt: u64) -> Self {
+        if INLINE > 0 && len <= INLINE {
+            return Self::inline(salt);
+        }
+        Self::from_slots(Vec::with_capacity(len), len, salt)
+    }
+
+    /// Returns a table without any slot, inline or not.
+    fn empty() -> Self {
+        Self::from_slots(Vec::new(), 0, 0)
+    }
+
+    /// Like [`with_slots`], but returns an error instead of aborting if the
+    /// allocation fails.
+    ///
+    /// [`with_slots`]: RawTable::with_slots
+    fn try_with_slots(len: usize, salt: u64) -> Result<Self, TryReserveError> {
+        if INLINE > 0 && len <= INLINE {
+            return Ok(Self::inline(salt));
+        }
+        let mut slots = Vec::new();
+        slots.try_reserve_exact(len)?;
+        Ok(Self::from_slots(slots, len, salt))
+    }
+
+    /// Fills `slots`, which must have room for `len` slots, with empty ones.
+    fn from_slots(mut slots: Vec<Slot<K, V>>, len: usize, salt: u64) -> Self {
+        debug_assert!(len == 0 || len.is_power_of_two());
+        slots.resize_with(len, || Slot::Empty);
+        Self {
+            slots: Slots::Heap(slots),
+            mask: len.saturating_sub(1),
             size: 0,
+            deleted: 0,
+            salt,
         }
     }
 
-    fn hash(&self, key: &K) -> usize {
-        let mut hasher = SimpleHasher(self.hash_salt);
-        key.hash(&mut hasher);
-        (hasher.finish() as usize) % self.items.len()
+    /// Returns a table of `INLINE` empty slots stored in place.
+    fn inline(salt: u64) -> Self {
+        const {
+            assert!(
+                INLINE == 0 || INLINE.is_power_of_two(),
+                "INLINE must be a power of two"
+            )
+        };
+        Self {
+            slots: Slots::Inline(array::from_fn(|_| Slot::Empty)),
+            mask: INLINE.saturating_sub(1),
+            size: 0,
+            deleted: 0,
+            salt,
+        }
     }
 
-    pub fn insert(&mut self, key: K, value: V) {
-        if self.size * 4 >= self.items.len() * 3 {
-            self.resize();
+    /// Returns `true` if the slots are stored in place.
+    fn is_inline(&self) -> bool {
+        INLINE > 0 && matches!(self.slots, Slots::Inline(_))
+    }
+
+    /// Returns the slot where the probe sequence of `hash` starts.
+    ///
+    /// A folded 64x64-bit multiplication lets every bit of the hash and of
+    /// the salt reach the low bits kept by the mask. Salt 0 leaves the hash
+    /// unchanged.
+    fn home(&self, hash: u64) -> usize {
+        let product = hash as u128 * (self.salt | 1) as u128;
+        (product as u64 ^ (product >> 64) as u64) as usize & self.mask
+    }
+
+    /// Returns `true` if live entries and tombstones have reached the load
+    /// factor of `load_factor` percent.
+    pub(crate) fn is_overloaded(&self, load_factor: u8) -> bool {
+        self.size + self.deleted >= max_load(self.slots.len(), load_factor)
+    }
+
+    /// Returns the index of the slot holding `key`. `hash` must be the hash
+    /// of `key`.
+    fn find<Q>(&self, hash: u64, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq,
+    {
+        self.find_with(hash, |k| k.borrow() == key)
+    }
+
+    /// Returns the index of the first slot with the given `hash` whose key
+    /// satisfies `is_match`.
+    pub(crate) fn find_with(
+        &self,
+        hash: u64,
+        mut is_match: impl FnMut(&K) -> bool,
+    ) -> Option<usize> {
+        if self.size == 0 {
+            return None;
+        }
+        let mut idx = self.home(hash);
+        for _ in 0..self.slots.len() {
+            match &self.slots[idx] {
+                Slot::Empty => return None,
+                Slot::Full(b) if b.hash == hash && is_match(&b.key) => return Some(idx),
+                _ => {}
+            }
+            idx = (idx + 1) & self.mask;
         }
+        None
+    }
 
-        let idx = self.hash(&key);
-        for entry in &mut self.items[idx] {
-            if entry.0 == key {
-                entry.1 = value;
-                return;
+    /// Returns `Ok` with the index of the slot holding `key`, or `Err` with
+    /// the index of the slot where it should be inserted. `hash` must be the
+    /// hash of `key`.
+    ///
+    /// The table must not be full, which [`HashMap::grow_if_needed`]
+    /// guarantees.
+    fn find_or_vacant(&self, hash: u64, key: &K) -> Result<usize, usize>
+    where
+        K: Eq,
+    {
+        let mut idx = self.home(hash);
+        let mut vacant = None;
+        for _ in 0..self.slots.len() {
+            match &self.slots[idx] {
+                Slot::Empty => return Err(vacant.unwrap_or(idx)),
+                Slot::Deleted => {
+                    vacant.get_or_insert(idx);
+                }
+                Slot::Full(b) if b.hash == hash && b.key == *key => return Ok(idx),
+                Slot::Full(..) => {}
             }
+            idx = (idx + 1) & self.mask;
+        }
+        Err(vacant.expect("no vacant slot in a full table"))
+    }
+
+    /// Inserts an entry whose key is known not to be in the table. `hash`
+    /// must be the hash of `key`.
+    pub(crate) fn insert_hashed(&mut self, hash: u64, key: K, value: V) {
+        self.insert_unique(Bucket { hash, key, value });
+    }
+
+    /// Inserts an entry whose key is known not to be in the table, at the
+    /// first vacant slot of its probe sequence.
+    fn insert_unique(&mut self, bucket: Bucket<K, V>) {
+        let mut idx = self.home(bucket.hash);
+        while let Slot::Full(..) = self.slots[idx] {
+            idx = (idx + 1) & self.mask;
+        }
+        self.put(idx, bucket.hash, bucket.key, bucket.value);
+    }
+
+    /// Returns the number of slots probed to reach the entry at `idx`, 1 if
+    /// it sits in its home slot.
+    fn probe_len(&self, idx: usize, hash: u64) -> usize {
+        (idx.wrapping_sub(self.home(hash)) & self.mask) + 1
+    }
+
+    pub(crate) fn get(&self, idx: usize) -> (&K, &V) {
+        match &self.slots[idx] {
+            Slot::Full(b) => (&b.key, &b.value),
+            _ => unreachable!("slot {} is not occupied", idx),
+        }
+    }
+
+    fn get_mut(&mut self, idx: usize) -> (&K, &mut V) {
+        match &mut self.slots[idx] {
+            Slot::Full(b) => (&b.key, &mut b.value),
+            _ => unreachable!("slot {} is not occupied", idx),
         }
+    }
 
-        self.items[idx].push((key, value));
+    /// Fills the vacant slot `idx` (empty or tombstone).
+    fn put(&mut self, idx: usize, hash: u64, key: K, value: V) -> &mut V {
+        let slot = &mut self.slots[idx];
+        if let Slot::Deleted = slot {
+            self.deleted -= 1;
+        }
+        *slot = Slot::Full(Bucket { hash, key, value });
         self.size += 1;
+        match slot {
+            Slot::Full(b) => &mut b.value,
+            _ => unreachable!(),
+        }
     }
 
-    fn resize(&mut self) {
-        let new_cap = self.items.len() * 2;
-        let mut new_items = Vec::with_capacity(new_cap);
-        for _ in 0..new_cap {
-            new_items.push(Vec::new());
+    /// Takes the entry out of the occupied slot `idx`, leaving a tombstone.
+    fn take_bucket(&mut self, idx: usize) -> Bucket<K, V> {
+        match core::mem::replace(&mut self.slots[idx], Slot::Deleted) {
+            Slot::Full(b) => {
+                self.size -= 1;
+                self.deleted += 1;
+                b
+            }
+            _ => unreachable!("slot {} is not occupied", idx),
+        }
+    }
+
+    pub(crate) fn take(&mut self, idx: usize) -> (K, V) {
+        let b = self.take_bucket(idx);
+        (b.key, b.value)
+    }
+
+    /// Returns the key of the occupied slot `idx` for in-place updates,
+    /// which must keep the key's hash unchanged.
+    pub(crate) fn key_mut(&mut self, idx: usize) -> &mut K {
+        match &mut self.slots[idx] {
+            Slot::Full(b) => &mut b.key,
+            _ => unreachable!("slot {} is not occupied", idx),
         }
+    }
+
+    /// An iterator over all keys for in-place updates, which must keep their
+    /// hashes unchanged.
+    pub(crate) fn keys_mut(&mut self) -> impl Iterator<Item = &mut K> {
+        self.slots.iter_mut().filter_map(|slot| match slot {
+            Slot::Full(b) => Some(&mut b.key),
+            _ => None,
+        })
+    }
 
-        for item in self.items.drain(..) {
-            for (k, v) in item {
-                let mut hasher = SimpleHasher(self.hash_salt);
-                k.hash(&mut hasher);
-                let idx = (hasher.finish() as usize) % new_cap;
-                new_items[idx].push((k, v));
+    fn retain<F: FnMut(&K, &mut V) -> bool>(&mut self, f: &mut F) {
+        for idx in 0..self.slots.len() {
+            if let Slot::Full(b) = &mut self.slots[idx] {
+                if !f(&b.key, &mut b.value) {
+                    self.take(idx);
+                }
             }
         }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        for slot in self.slots.iter_mut() {
+            *slot = Slot::Empty;
+        }
+        self.size = 0;
+        self.deleted = 0;
+    }
+}
+
+/// The load factor of a new [`HashMap`], in percent.
+pub(crate) const DEFAULT_LOAD_FACTOR: u8 = 75;
+
+/// Bounds of [`HashMap::set_load_factor_percent`]. Below 100%, a table
+/// always keeps an empty slot to terminate probe sequences.
+const MIN_LOAD_FACTOR: u8 = 10;
+const MAX_LOAD_FACTOR: u8 = 95;
+
+/// Returns how many entries and tombstones `slots` slots can hold within a
+/// load factor of `load_factor` percent.
+fn max_load(slots: usize, load_factor: u8) -> usize {
+    let pct = load_factor as usize;
+    slots / 100 * pct + slots % 100 * pct / 100
+}
+
+/// Returns the minimal power-of-two number of slots able to hold `entries`
+/// elements within the load factor, or 0 for no entries.
+pub(crate) fn buckets_for(entries: usize, load_factor: u8) -> usize {
+    checked_buckets_for(entries, load_factor).expect("capacity overflow")
+}
 
-        self.items = new_items;
+/// Like [`buckets_for`], but returns [`None`] if the count overflows.
+fn checked_buckets_for(entries: usize, load_factor: u8) -> Option<usize> {
+    if entries == 0 {
+        return Some(0);
     }
+    let mut buckets =
+        (entries.checked_mul(100)? / load_factor as usize).checked_next_power_of_two()?;
+    while max_load(buckets, load_factor) < entries {
+        buckets = buckets.checked_mul(2)?;
+    }
+    Some(buckets)
+}
 
-    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
-        self.items
-            .iter()
-            .flat_map(|item| item.iter().map(|(k, v)| (k, v)))
+/// Iterators over the slots of the old table, then the current one.
+type SlotsIter<'a, K, V> = Chain<slice::Iter<'a, Slot<K, V>>, slice::Iter<'a, Slot<K, V>>>;
+type SlotsIterMut<'a, K, V> = Chain<slice::IterMut<'a, Slot<K, V>>, slice::IterMut<'a, Slot<K, V>>>;
+type SlotsIntoIter<K, V, const INLINE: usize> =
+    Chain<OwnedSlots<K, V, INLINE>, OwnedSlots<K, V, INLINE>>;
+
+/// Number of slots of the old table migrated by each operation during an
+/// incremental resize.
+///
+/// With 4 slots per operation and the default load factor, a migration always
+/// completes before the new table reaches the load factor, whether it doubled
+/// or only purges tombstones. With a low load factor, the next growth may
+/// have to finish the migration at once.
+const RESIZE_STEP: usize = 4;
+
+/// Occupancy and probe-length statistics of a [`HashMap`], returned by
+/// [`HashMap::bucket_stats`].
+///
+/// The map has no chains: the chain of an entry is the run of slots probed
+/// from its home slot up to its own, so `max_chain` is the worst-case number
+/// of slots a successful lookup visits.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BucketStats {
+    /// Number of slots, in both tables during an incremental resize.
+    pub buckets: usize,
+    /// Number of slots holding an entry.
+    pub occupied: usize,
+    /// Number of never-used slots. Slots that are neither occupied nor empty
+    /// are tombstones.
+    pub empty: usize,
+    /// Length of the longest probe sequence, 0 for an empty map.
+    pub max_chain: usize,
+    /// Mean probe sequence length times 100, 0 for an empty map.
+    pub avg_chain_x100: usize,
+}
+
+/// The approximate heap footprint of a [`HashMap`], returned by
+/// [`HashMap::memory_footprint`].
+///
+/// Entries are stored inline in the slots of the table, so the table bytes
+/// include them. Heap memory owned by the keys and values themselves, such
+/// as the buffer of a `String`, is only counted in `entry_bytes`, when a
+/// callback reports it through [`HashMap::memory_footprint_with`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MapFootprint {
+    /// Heap bytes of the slot tables, in both tables during an incremental
+    /// resize.
+    pub bucket_table_bytes: usize,
+    /// Heap bytes owned by the keys and values, outside the table.
+    pub entry_bytes: usize,
+    /// Bytes of the table in slots holding no entry, empty or tombstones.
+    pub spare_capacity_bytes: usize,
+}
+
+impl MapFootprint {
+    /// Returns the total number of heap bytes the map is responsible for.
+    pub fn total(&self) -> usize {
+        self.bucket_table_bytes + self.entry_bytes
+    }
+}
+
+/// An inconsistency in the internal state of a [`HashMap`], as reported by
+/// [`HashMap::check_invariants`].
+///
+/// Slot indices count the slots of the table being migrated first, followed
+/// by those of the current table.
+#[cfg(any(test, feature = "debug-validate"))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InvariantViolation {
+    /// The entry count of a table differs from its number of occupied slots.
+    SizeMismatch { recorded: usize, actual: usize },
+    /// The tombstone count of a table differs from its number of tombstones.
+    DeletedMismatch { recorded: usize, actual: usize },
+    /// The cached hash of the entry in slot `index` is not the hash of its
+    /// key.
+    StaleHash { index: usize },
+    /// The entry in slot `index` is past an empty slot of its probe
+    /// sequence, so lookups miss it.
+    Unreachable { index: usize },
+    /// The key in slot `index` is also stored in an earlier slot.
+    DuplicateKey { index: usize },
+    /// Slot `index` of the table being migrated is below the migration
+    /// cursor, yet still occupied.
+    Unmigrated { index: usize },
+}
+
+/// A hash map implemented with open addressing and linear probing.
+///
+/// All entries live in a single flat table, so the map costs one heap
+/// allocation regardless of its size. Removed entries leave tombstones, which
+/// count towards the load factor and are dropped on the next rehash.
+///
+/// Each entry caches the 64-bit hash of its key, at a cost of 8 bytes per
+/// slot. Lookups compare keys only when the hashes match, and resizing never
+/// hashes a key again.
+///
+/// Since moving entries between tables calls neither `Hash` nor `Eq`, a key
+/// whose `Hash` or `Eq` panics can only unwind out of a lookup, before the
+/// map is modified. Every entry survives, including while a resize is in
+/// progress.
+///
+/// Growing is incremental: the full table is kept aside while a new one is
+/// allocated, and every later insertion or removal moves a few of its
+/// entries over, so that no single operation pays for rehashing the whole
+/// map. Lookups check both tables until the migration completes. Use
+/// [`finish_resize`] to complete it eagerly at a convenient time.
+///
+/// Each table placed by a resize mixes a new salt into the hashes of its
+/// keys, drawn at random unless the map was given its seed or hash state.
+/// Keys that were made to collide in one table are thus scattered in the
+/// next one. See [`rehash_with_new_salt`].
+///
+/// Small maps can keep their table in place and skip the allocation
+/// altogether, see [`with_hasher_inline`].
+///
+/// The map and its iterators and entries own or borrow plain `Vec` or array
+/// storage and hold no raw pointers, so they are `Send` and `Sync` exactly
+/// when the keys, values and hash state are. A map can thus be shared behind
+/// a lock as soon as its contents can.
+///
+/// [`finish_resize`]: HashMap::finish_resize
+/// [`rehash_with_new_salt`]: HashMap::rehash_with_new_salt
+/// [`with_hasher_inline`]: HashMap::with_hasher_inline
+pub struct HashMap<K, V, S = RandomSeededState, const INLINE: usize = 0> {
+    table: RawTable<K, V, INLINE>,
+    /// The table being migrated into `table`, empty when not resizing.
+    old: RawTable<K, V, INLINE>,
+    /// Slots of `old` below this index have been migrated.
+    migrated: usize,
+    /// Load factor in percent, between `MIN_LOAD_FACTOR` and
+    /// `MAX_LOAD_FACTOR`.
+    load_factor: u8,
+    /// Whether removals shrink the table, see [`HashMap::shrink_threshold`].
+    auto_shrink: bool,
+    /// The table size requested at construction, which shrinking keeps.
+    min_slots: usize,
+    /// Number of table salts drawn so far, see `new_salt`.
+    salts: u64,
+    /// Whether salts are drawn at random rather than derived from
+    /// `hash_builder`, see `new_salt`.
+    random_salts: bool,
+    hash_builder: S,
+}
+
+impl<K: Eq + Hash, V> HashMap<K, V, RandomSeededState> {
+    /// Creates an empty map.
+    ///
+    /// The table is not allocated until the first insertion. The random seed
+    /// of the hash state is still drawn right away, which does not allocate.
+    pub fn new() -> Self {
+        Self::with_hasher(RandomSeededState::new()).with_random_salts()
+    }
+
+    /// Creates an empty map with room for at least `cap` elements before it
+    /// resizes. `with_capacity(0)` does not allocate.
+    pub fn with_capacity(cap: usize) -> Self {
+        Self::with_capacity_and_hasher(cap, RandomSeededState::new()).with_random_salts()
+    }
+
+    /// Creates an empty map with room for at least `cap` elements, which
+    /// resizes once `pct` percent of its table is used.
+    ///
+    /// See [`set_load_factor_percent`] for the valid range of `pct`.
+    ///
+    /// [`set_load_factor_percent`]: HashMap::set_load_factor_percent
+    pub fn with_capacity_and_load_factor(cap: usize, pct: u8) -> Self {
+        Self::with_parts(cap, pct, RandomSeededState::new()).with_random_salts()
+    }
+
+    /// Creates a map from pairs sorted by key, as exported by
+    /// [`to_sorted_vec`], with a single allocation for the table.
+    ///
+    /// In debug builds, panics if the keys are not strictly increasing.
+    ///
+    /// [`to_sorted_vec`]: HashMap::to_sorted_vec
+    pub fn from_sorted_pairs(pairs: Vec<(K, V)>) -> Self
+    where
+        K: Ord,
+    {
+        debug_assert!(
+            pairs.windows(2).all(|w| w[0].0 < w[1].0),
+            "pairs are not sorted and deduplicated"
+        );
+        pairs.into_iter().collect()
+    }
+
+    /// Creates a map from a slice of key-value pairs, cloning them. The
+    /// table is sized once for all pairs; for duplicate keys, the last pair
+    /// wins.
+    pub fn from_pairs(pairs: &[(K, V)]) -> Self
+    where
+        K: Clone,
+        V: Clone,
+    {
+        pairs.iter().cloned().collect()
+    }
+
+    /// Creates an empty map whose hash state is seeded with `seed` instead
+    /// of a random value.
+    ///
+    /// Maps with equal seeds that go through the same insertions and
+    /// removals iterate in the same order. Keep the seed secret if the keys
+    /// come from untrusted input: knowing it makes crafting collisions easy.
+    pub fn with_seed(seed: u128) -> Self {
+        Self::with_hasher(RandomSeededState::with_seed(seed))
+    }
+
+    /// Creates an empty map with room for at least `cap` elements, whose hash
+    /// state is seeded with `seed`. See [`with_seed`].
+    ///
+    /// [`with_seed`]: HashMap::with_seed
+    pub fn with_capacity_and_seed(cap: usize, seed: u128) -> Self {
+        Self::with_capacity_and_hasher(cap, RandomSeededState::with_seed(seed))
+    }
+
+    /// Returns a builder to configure a map, starting from the defaults of
+    /// [`new`].
+    ///
+    /// [`new`]: HashMap::new
+    pub fn builder() -> HashMapBuilder<K, V> {
+        HashMapBuilder {
+            cap: 0,
+            load_factor: DEFAULT_LOAD_FACTOR,
+            auto_shrink: false,
+            hash_builder: RandomSeededState::new(),
+            random_salts: true,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S> {
+    /// Creates an empty map which will use the given hash builder to hash
+    /// keys. Does not allocate until the first insertion.
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self::with_capacity_and_hasher(0, hash_builder)
+    }
+
+    /// Creates an empty map with room for at least `cap` elements, using
+    /// `hash_builder` to hash keys.
+    pub fn with_capacity_and_hasher(cap: usize, hash_builder: S) -> Self {
+        Self::with_parts(cap, DEFAULT_LOAD_FACTOR, hash_builder)
+    }
+
+    /// Creates an empty map hashing keys like `other`, by cloning its hash
+    /// builder.
+    ///
+    /// A hash computed once with either map's [`hasher`] then serves both,
+    /// e.g. to move an entry between them through the raw API without
+    /// hashing its key again.
+    ///
+    /// [`hasher`]: HashMap::hasher
+    pub fn with_hasher_from<W>(other: &HashMap<K, W, S>) -> Self
+    where
+        S: Clone,
+    {
+        Self::with_hasher(other.hash_builder.clone())
+    }
+}
+
+impl<K: Eq + Hash, V, const INLINE: usize> HashMap<K, V, RandomSeededState, INLINE> {
+    /// Creates an empty map holding up to `INLINE` slots in place, see
+    /// [`with_hasher_inline`].
+    ///
+    /// [`with_hasher_inline`]: HashMap::with_hasher_inline
+    pub fn new_inline() -> Self {
+        Self::with_hasher_inline(RandomSeededState::new()).with_random_salts()
+    }
+}
+
+impl<K, V, S, const INLINE: usize> HashMap<K, V, S, INLINE> {
+    /// Creates an empty map whose table of `INLINE` slots is stored in the
+    /// map itself, using `hash_builder` to hash keys.
+    ///
+    /// Such a map does not allocate until it outgrows the inline table, and
+    /// moves back into it if shrunk far enough, by [`shrink_to_fit`] or
+    /// automatic shrinking. Both layouts behave the same otherwise: lookups
+    /// probe the inline slots like any table. `INLINE` must be a power of
+    /// two, and every slot adds the size of an entry and its hash to the
+    /// map.
+    ///
+    /// [`shrink_to_fit`]: HashMap::shrink_to_fit
+    pub fn with_hasher_inline(hash_builder: S) -> Self {
+        Self::with_parts(0, DEFAULT_LOAD_FACTOR, hash_builder)
+    }
+
+    fn with_parts(cap: usize, pct: u8, hash_builder: S) -> Self {
+        let load_factor = pct.clamp(MIN_LOAD_FACTOR, MAX_LOAD_FACTOR);
+        // An inline table costs nothing to set up, so start with it.
+        let min_slots = buckets_for(cap, load_factor).max(INLINE);
+        HashMap {
+            table: RawTable::with_slots(min_slots, 0),
+            old: RawTable::empty(),
+            migrated: 0,
+            load_factor,
+            auto_shrink: false,
+            min_slots,
+            salts: 0,
+            random_salts: false,
+            hash_builder,
+        }
+    }
+
+    /// Makes the map draw the salts of its tables at random, unless the
+    /// `deterministic-hash` feature asks for reproducible maps.
+    fn with_random_salts(mut self) -> Self {
+        self.random_salts = !cfg!(feature = "deterministic-hash");
+        self
+    }
+}
+
+/// A builder for a [`HashMap`], setting every construction knob of the map
+/// by name.
+///
+/// This is created by the [`builder`] method on [`HashMap`]. Settings not
+/// given keep the defaults of [`HashMap::new`].
+///
+/// [`builder`]: HashMap::builder
+pub struct HashMapBuilder<K, V, S = RandomSeededState> {
+    cap: usize,
+    load_factor: u8,
+    auto_shrink: bool,
+    hash_builder: S,
+    /// Whether `hash_builder` is still the random default.
+    random_salts: bool,
+    marker: PhantomData<fn() -> (K, V)>,
+}
+
+impl<K, V> HashMapBuilder<K, V, RandomSeededState> {
+    /// Seeds the hash state with `seed`, see [`HashMap::with_seed`].
+    pub const fn seed(mut self, seed: u128) -> Self {
+        self.hash_builder = RandomSeededState::with_seed(seed);
+        self.random_salts = false;
+        self
+    }
+}
+
+impl<K, V, S> HashMapBuilder<K, V, S> {
+    /// Makes room for at least `cap` elements before the map resizes.
+    pub const fn capacity(mut self, cap: usize) -> Self {
+        self.cap = cap;
+        self
+    }
+
+    /// Sets the load factor in percent, see
+    /// [`HashMap::set_load_factor_percent`].
+    pub const fn load_factor(mut self, pct: u8) -> Self {
+        self.load_factor = pct;
+        self
+    }
+
+    /// Enables or disables automatic shrinking, see
+    /// [`HashMap::shrink_threshold`].
+    pub const fn shrink_threshold(mut self, enable: bool) -> Self {
+        self.auto_shrink = enable;
+        self
+    }
+
+    /// Hashes keys with `hash_builder` instead.
+    pub fn hasher<T>(self, hash_builder: T) -> HashMapBuilder<K, V, T> {
+        HashMapBuilder {
+            cap: self.cap,
+            load_factor: self.load_factor,
+            auto_shrink: self.auto_shrink,
+            hash_builder,
+            random_salts: false,
+            marker: PhantomData,
+        }
+    }
+
+    /// Creates the map.
+    pub fn build(self) -> HashMap<K, V, S> {
+        let mut map = HashMap::with_parts(self.cap, self.load_factor, self.hash_builder);
+        if self.random_salts {
+            map = map.with_random_salts();
+        }
+        map.auto_shrink = self.auto_shrink;
+        map
+    }
+}
+
+impl<K: Eq + Hash, V, S: BuildHasher, const INLINE: usize> HashMap<K, V, S, INLINE> {
+    fn hash<Q: ?Sized + Hash>(&self, key: &Q) -> u64 {
+        self.hash_builder.hash_one(key)
+    }
+
+    /// Returns the salt of a new table.
+    ///
+    /// Maps with a random hash state draw it from [`axhal::misc::random`],
+    /// so that it cannot be predicted from earlier ones. Maps given their
+    /// seed or hash state derive it from that state and the number of salts
+    /// drawn instead, so that equal maps keep placing keys alike.
+    ///
+    /// [`axhal::misc::random`]: arceos_api::modules::axhal::misc::random
+    fn new_salt(&mut self) -> u64 {
+        self.salts += 1;
+        if self.random_salts {
+            let random = random_seed();
+            (random ^ random >> 64) as u64
+        } else {
+            self.hash(&self.salts)
+        }
+    }
+
+    /// Returns whether `key` is in the old table, and the index of its slot
+    /// there.
+    fn locate<Q>(&self, key: &Q) -> Option<(bool, usize)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let hash = self.hash(key);
+        if let Some(idx) = self.table.find(hash, key) {
+            return Some((false, idx));
+        }
+        Some((true, self.old.find(hash, key)?))
+    }
+
+    /// Returns the table holding `key`, and the index of its slot there.
+    fn find_mut<Q>(&mut self, key: &Q) -> Option<(&mut RawTable<K, V, INLINE>, usize)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        match self.locate(key)? {
+            (false, idx) => Some((&mut self.table, idx)),
+            (true, idx) => Some((&mut self.old, idx)),
+        }
+    }
+
+    /// Inserts a key-value pair into the map.
+    ///
+    /// If the map did not have this key present, [`None`] is returned.
+    /// Otherwise the value is updated and the old value is returned.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let old = Self::insert_entry(self.entry(key), value);
+        self.debug_validate();
+        old
+    }
+
+    /// Inserts a key-value pair into the map, returning an error instead of
+    /// aborting if the table needs to grow and the allocation fails.
+    ///
+    /// On error, the map is left unchanged and `key` and `value` are
+    /// dropped.
+    pub fn try_insert(&mut self, key: K, value: V) -> Result<Option<V>, TryReserveError> {
+        self.migrate(RESIZE_STEP);
+        if self.table.is_overloaded(self.load_factor) {
+            // Replacing a value needs no room, so only grow for a new key.
+            if let Some(old) = self.get_mut(&key) {
+                return Ok(Some(core::mem::replace(old, value)));
+            }
+            self.try_resize()?;
+        }
+        let hash = self.hash(&key);
+        let old = Self::insert_entry(self.entry_in_place(hash, key), value);
+        self.debug_validate();
+        Ok(old)
+    }
+
+    fn insert_entry(entry: Entry<'_, K, V, INLINE>, value: V) -> Option<V> {
+        match entry {
+            Entry::Occupied(mut entry) => Some(entry.insert(value)),
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+                None
+            }
+        }
+    }
+
+    /// Returns a mutable reference to the value of `key`, inserting
+    /// `default` first if the key is missing.
+    pub fn get_or_insert(&mut self, key: K, default: V) -> &mut V {
+        self.entry(key).or_insert(default)
+    }
+
+    /// Returns a mutable reference to the value of `key`, inserting the
+    /// result of `f` first if the key is missing. `f` is not called if the
+    /// key is present.
+    ///
+    /// The key is hashed once: like [`entry`], this grows the table before
+    /// looking for a slot, so the slot found stays valid for the insertion.
+    ///
+    /// [`entry`]: HashMap::entry
+    pub fn get_or_insert_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
+        self.entry(key).or_insert_with(f)
+    }
+
+    /// Gets the given key's corresponding entry in the map for in-place
+    /// manipulation.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, INLINE> {
+        // Grow up front, so that a vacant entry can be filled without
+        // rehashing the table it has already found a slot in.
+        self.grow_if_needed();
+        let hash = self.hash(&key);
+        self.entry_in_place(hash, key)
+    }
+
+    /// Like [`entry`], but never grows the table, which must not be full.
+    /// `hash` must be the hash of `key`.
+    ///
+    /// [`entry`]: HashMap::entry
+    fn entry_in_place(&mut self, hash: u64, key: K) -> Entry<'_, K, V, INLINE> {
+        match self.table.find_or_vacant(hash, &key) {
+            Ok(index) => Entry::Occupied(OccupiedEntry {
+                table: &mut self.table,
+                index,
+                key,
+            }),
+            Err(index) => match self.old.find(hash, &key) {
+                Some(index) => Entry::Occupied(OccupiedEntry {
+                    table: &mut self.old,
+                    index,
+                    key,
+                }),
+                None => Entry::Vacant(VacantEntry {
+                    table: &mut self.table,
+                    index,
+                    hash,
+                    key,
+                }),
+            },
+        }
+    }
+
+    fn grow_if_needed(&mut self) {
+        self.migrate(RESIZE_STEP);
+        if self.table.is_overloaded(self.load_factor) {
+            self.resize();
+        }
+    }
+
+    /// Reserves capacity for at least `additional` more elements, so that
+    /// they can be inserted without resizing.
+    ///
+    /// The table is grown at most once, eagerly, finishing any incremental
+    /// resize in progress.
+    pub fn reserve(&mut self, additional: usize) {
+        let needed = self
+            .len()
+            .checked_add(additional)
+            .expect("capacity overflow");
+        self.finish_resize();
+        // Tombstones take up room too, so count them against the capacity.
+        if needed
+            .checked_add(self.table.deleted)
+            .expect("capacity overflow")
+            > self.capacity()
+        {
+            let buckets = buckets_for(needed, self.load_factor).max(self.table.slots.len());
+            let salt = self.new_salt();
+            self.rehash(RawTable::with_slots(buckets, salt));
+        }
+        self.debug_validate();
+    }
+
+    /// Tries to reserve capacity for at least `additional` more elements, so
+    /// that they can be inserted without resizing.
+    ///
+    /// Unlike [`reserve`], this returns an error instead of panicking or
+    /// aborting if the capacity overflows or the allocation fails. The map is
+    /// left unchanged in that case.
+    ///
+    /// [`reserve`]: HashMap::reserve
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        // An overflowing count asks `Vec` for an impossible allocation, which
+        // reports it as a capacity overflow.
+        let needed = self.len().checked_add(additional);
+        let buckets = needed
+            .and_then(|needed| checked_buckets_for(needed, self.load_factor))
+            .unwrap_or(usize::MAX);
+        self.finish_resize();
+        if needed
+            .and_then(|needed| needed.checked_add(self.table.deleted))
+            .map_or(true, |needed| needed > self.capacity())
+        {
+            let table =
+                RawTable::try_with_slots(buckets.max(self.table.slots.len()), self.new_salt())?;
+            self.rehash(table);
+        }
+        Ok(())
+    }
+
+    /// Shrinks the table as much as possible while still holding all
+    /// current elements within the load factor.
+    pub fn shrink_to_fit(&mut self) {
+        self.finish_resize();
+        let buckets = buckets_for(self.table.size, self.load_factor).max(INLINE);
+        if buckets < self.table.slots.len() {
+            let salt = self.new_salt();
+            self.rehash(RawTable::with_slots(buckets, salt));
+        }
+        self.debug_validate();
+    }
+
+    /// Sets the load factor of the map: the table resizes once `pct` percent
+    /// of its slots are used. `pct` is clamped to `10..=95`.
+    ///
+    /// A high load factor saves memory at the cost of longer probe
+    /// sequences; a low one speeds up lookups. If the map is already above
+    /// the new load factor, it is resized right away.
+    pub fn set_load_factor_percent(&mut self, pct: u8) {
+        self.load_factor = pct.clamp(MIN_LOAD_FACTOR, MAX_LOAD_FACTOR);
+        // Entries still in the old table count too.
+        self.finish_resize();
+        if self.table.is_overloaded(self.load_factor) {
+            let buckets = buckets_for(self.len() + 1, self.load_factor);
+            let buckets = buckets.max(self.table.slots.len());
+            let salt = self.new_salt();
+            self.rehash(RawTable::with_slots(buckets, salt));
+        }
+    }
+
+    /// Starts migrating the table into a larger one, or into one of the same
+    /// size if most of its load is tombstones. The new table gets a fresh
+    /// salt.
+    fn resize(&mut self) {
+        // Only one migration at a time. `RESIZE_STEP` makes this a no-op in
+        // practice.
+        self.finish_resize();
+        let table = RawTable::with_slots(self.grown_len(), self.new_salt());
+        self.start_migration(table);
+    }
+
+    /// Like [`resize`], but returns an error instead of aborting if the
+    /// allocation fails.
+    ///
+    /// [`resize`]: HashMap::resize
+    fn try_resize(&mut self) -> Result<(), TryReserveError> {
+        self.finish_resize();
+        let table = RawTable::try_with_slots(self.grown_len(), self.new_salt())?;
+        self.start_migration(table);
+        Ok(())
+    }
+
+    /// Returns the number of slots of the table replacing the current one.
+    fn grown_len(&self) -> usize {
+        let len = self.table.slots.len();
+        // A table shrunk to nothing starts over from the default size.
+        if self.table.size >= self.table.deleted {
+            (len * 2).max(16)
+        } else {
+            len
+        }
+    }
+
+    fn start_migration(&mut self, table: RawTable<K, V, INLINE>) {
+        let old = core::mem::replace(&mut self.table, table);
+        if old.size > 0 {
+            self.old = old;
+            self.migrated = 0;
+        }
+    }
+
+    /// Rebuilds the table in place under a fresh salt, scattering the
+    /// entries to new slots. Tombstones are purged on the way.
+    ///
+    /// The map already draws a new salt whenever it resizes. Call this to
+    /// force one, e.g. when [`bucket_stats`] reports probe sequences far
+    /// longer than expected, which hints at keys crafted to collide.
+    ///
+    /// Unlike a resize, this hashes every key again instead of trusting the
+    /// cached hashes, which also repairs hashes passed wrong to
+    /// [`raw_entry_insert`]. Keys whose full hashes collide under the hash
+    /// state still collide afterwards: only a map with a new seed separates
+    /// them.
+    ///
+    /// [`bucket_stats`]: HashMap::bucket_stats
+    /// [`raw_entry_insert`]: HashMap::raw_entry_insert
+    pub fn rehash_with_new_salt(&mut self) {
+        self.finish_resize();
+        // Hash every key before moving any, so that a panicking `Hash`
+        // leaves the map as it was.
+        let hashes: Vec<u64> = self
+            .table
+            .slots
+            .iter()
+            .filter_map(|slot| match slot {
+                Slot::Full(b) => Some(self.hash(&b.key)),
+                _ => None,
+            })
+            .collect();
+        let salt = self.new_salt();
+        let table = RawTable::with_slots(self.table.slots.len(), salt);
+        let old = core::mem::replace(&mut self.table, table);
+        let buckets = old.slots.into_iter().filter_map(|slot| match slot {
+            Slot::Full(b) => Some(b),
+            _ => None,
+        });
+        for (mut b, hash) in buckets.zip(hashes) {
+            b.hash = hash;
+            self.table.insert_unique(b);
+        }
+        self.debug_validate();
+    }
+
+    /// Returns a reference to the value corresponding to the key.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        Some(self.get_key_value(key)?.1)
+    }
+
+    /// Moves all elements of `other` into `self`, leaving `other` empty.
+    ///
+    /// Values from `other` win over those of equal keys already in `self`.
+    /// `self` reserves room for both maps up front, so that it resizes at
+    /// most once. `other` keeps its table for reuse, as with [`drain`].
+    ///
+    /// [`drain`]: HashMap::drain
+    pub fn append(&mut self, other: &mut Self) {
+        self.reserve(other.len());
+        for (k, v) in other.drain() {
+            self.insert(k, v);
+        }
+    }
+
+    /// Returns the stored key and a reference to the value corresponding to
+    /// the key.
+    ///
+    /// The stored key may be a distinct object from `key`, e.g. the
+    /// canonical `String` of an interning table probed with a `&str`.
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        match self.locate(key)? {
+            (false, idx) => Some(self.table.get(idx)),
+            (true, idx) => Some(self.old.get(idx)),
+        }
+    }
+
+    /// Returns a mutable reference to the value corresponding to the key.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let (table, idx) = self.find_mut(key)?;
+        Some(table.get_mut(idx).1)
+    }
+
+    /// Looks up an entry by a precomputed hash, without hashing any key.
+    ///
+    /// `hash` must be computed with the map's own hash state, e.g.
+    /// `map.hasher().hash_one(key)`; the slot is then selected from it the
+    /// same way as for a regular lookup. Returns the first entry with that
+    /// hash for which `is_match` returns `true`.
+    pub fn raw_get(&self, hash: u64, mut is_match: impl FnMut(&K) -> bool) -> Option<(&K, &V)> {
+        if let Some(idx) = self.table.find_with(hash, &mut is_match) {
+            return Some(self.table.get(idx));
+        }
+        let idx = self.old.find_with(hash, is_match)?;
+        Some(self.old.get(idx))
+    }
+
+    /// Inserts a key-value pair under a precomputed hash, without hashing
+    /// the key. Returns the old value if an equal key was stored under the
+    /// same hash.
+    ///
+    /// `hash` must be the hash of `key` computed with the map's own hash
+    /// state. Supplying a different hash is not undefined behavior, but the
+    /// entry may then be missed by regular lookups, and equal keys inserted
+    /// under different hashes become logical duplicates.
+    pub fn raw_entry_insert(&mut self, hash: u64, key: K, value: V) -> Option<V> {
+        self.grow_if_needed();
+        Self::insert_entry(self.entry_in_place(hash, key), value)
+    }
+
+    /// Returns mutable references to the values of `N` keys at once.
+    ///
+    /// Returns [`None`] if any key is missing or if any two keys are equal.
+    pub fn get_many_mut<Q, const N: usize>(&mut self, keys: [&Q; N]) -> Option<[&mut V; N]>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        Some(self.get_many_key_value_mut(keys)?.map(|(_, v)| v))
+    }
+
+    /// Returns the stored keys and mutable references to the values of `N`
+    /// keys at once.
+    ///
+    /// Returns [`None`] if any key is missing or if any two keys are equal.
+    pub fn get_many_key_value_mut<Q, const N: usize>(
+        &mut self,
+        keys: [&Q; N],
+    ) -> Option<[(&K, &mut V); N]>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let mut locations = [(false, 0); N];
+        for (i, key) in keys.into_iter().enumerate() {
+            let location = self.locate(key)?;
+            if locations[..i].contains(&location) {
+                return None;
+            }
+            locations[i] = location;
+        }
+        let table = self.table.slots.as_mut_ptr();
+        let old = self.old.slots.as_mut_ptr();
+        Some(locations.map(|(in_old, idx)| {
+            // SAFETY: `locate` returned in-bounds indices of occupied slots,
+            // and every key lives in exactly one slot of one table, so the
+            // checked-distinct locations refer to distinct entries. The
+            // references are therefore disjoint, and they borrow `self`
+            // mutably for as long as they live.
+            let slot = unsafe { &mut *if in_old { old } else { table }.add(idx) };
+            match slot {
+                Slot::Full(b) => (&b.key, &mut b.value),
+                _ => unreachable!("slot {} is not occupied", idx),
+            }
+        }))
+    }
+
+    /// Returns `true` if the map contains a value for the specified key.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Removes a key from the map, returning the value at the key if the key
+    /// was previously in the map.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.remove_entry(key).map(|(_, v)| v)
+    }
+
+    /// Removes a key from the map, returning the stored key and value if the
+    /// key was previously in the map.
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.migrate(RESIZE_STEP);
+        let (table, idx) = self.find_mut(key)?;
+        let entry = table.take(idx);
+        self.shrink_if_sparse();
+        self.debug_validate();
+        Some(entry)
+    }
+
+    /// Checks the internal consistency of the map, with one pass over both
+    /// tables that hashes every key again.
+    ///
+    /// Verifies that the entry and tombstone counts match the slots, that
+    /// every cached hash is the hash of its key, that every entry is reached
+    /// by probing from its home slot, that no key is stored twice, and that
+    /// migrated slots are empty.
+    ///
+    /// An incorrect `Hash` or `Eq` implementation, or a key inserted through
+    /// the raw API under a wrong hash, also shows up as a violation.
+    #[cfg(any(test, feature = "debug-validate"))]
+    pub fn check_invariants(&self) -> Result<(), InvariantViolation> {
+        for (table, offset) in [(&self.old, 0), (&self.table, self.old.slots.len())] {
+            let (mut size, mut deleted) = (0, 0);
+            for (idx, slot) in table.slots.iter().enumerate() {
+                let index = offset + idx;
+                let b = match slot {
+                    Slot::Empty => continue,
+                    Slot::Deleted => {
+                        deleted += 1;
+                        continue;
+                    }
+                    Slot::Full(b) => b,
+                };
+                size += 1;
+                if offset == 0 && idx < self.migrated {
+                    return Err(InvariantViolation::Unmigrated { index });
+                }
+                if self.hash(&b.key) != b.hash {
+                    return Err(InvariantViolation::StaleHash { index });
+                }
+                let home = table.home(b.hash);
+                let path = (idx.wrapping_sub(home) & table.mask) + 1;
+                if (0..path).any(|i| matches!(table.slots[(home + i) & table.mask], Slot::Empty)) {
+                    return Err(InvariantViolation::Unreachable { index });
+                }
+                // Entries still in the old table must not be in the new one.
+                if table.find(b.hash, &b.key) != Some(idx)
+                    || offset > 0 && self.old.find(b.hash, &b.key).is_some()
+                {
+                    return Err(InvariantViolation::DuplicateKey { index });
+                }
+            }
+            if size != table.size {
+                let recorded = table.size;
+                return Err(InvariantViolation::SizeMismatch {
+                    recorded,
+                    actual: size,
+                });
+            }
+            if deleted != table.deleted {
+                let recorded = table.deleted;
+                return Err(InvariantViolation::DeletedMismatch {
+                    recorded,
+                    actual: deleted,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Asserts [`check_invariants`] in debug builds with the
+    /// `debug-validate` feature, and does nothing otherwise.
+    ///
+    /// [`check_invariants`]: HashMap::check_invariants
+    #[inline]
+    fn debug_validate(&self) {
+        #[cfg(feature = "debug-validate")]
+        debug_assert_eq!(self.check_invariants(), Ok(()));
+    }
+
+    /// Returns the index of the bucket of `key` in the current table: the
+    /// slot where its probe sequence starts, whether the key is present or
+    /// not. Keys with the same bucket collide.
+    pub fn bucket_of<Q>(&self, key: &Q) -> usize
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.table.home(self.hash(key))
+    }
+
+    /// Enables or disables automatic shrinking.
+    ///
+    /// When enabled, a removal, [`retain`] or [`drain`] that leaves the map
+    /// below 1/8 of its capacity rebuilds the table at half its load factor,
+    /// and never below the capacity requested at construction. The gap
+    /// between shrinking at 12.5% and growing at the load factor keeps a map
+    /// hovering around one size from resizing back and forth.
+    ///
+    /// [`retain`]: HashMap::retain
+    /// [`drain`]: HashMap::drain
+    pub fn shrink_threshold(&mut self, enable: bool) {
+        self.auto_shrink = enable;
+        self.shrink_if_sparse();
+    }
+
+    fn shrink_if_sparse(&mut self) {
+        if !self.auto_shrink
+            || self.len() * 8 >= self.capacity()
+            || self.table.slots.len() <= self.min_slots
+        {
+            return;
+        }
+        self.finish_resize();
+        let slots = (buckets_for(self.len(), self.load_factor) * 2).max(self.min_slots);
+        if slots < self.table.slots.len() {
+            let salt = self.new_salt();
+            self.rehash(RawTable::with_slots(slots, salt));
+        }
+    }
+
+    /// Retains only the elements specified by the predicate.
+    ///
+    /// In other words, removes all pairs `(k, v)` for which `f(&k, &mut v)`
+    /// returns `false`. The predicate may also update the values it keeps.
+    pub fn retain<F: FnMut(&K, &mut V) -> bool>(&mut self, mut f: F) {
+        self.old.retain(&mut f);
+        self.table.retain(&mut f);
+        self.shrink_if_sparse();
+    }
+
+    /// Clears the map, returning all key-value pairs as an iterator. Keeps
+    /// the allocated table for reuse.
+    ///
+    /// If the returned iterator is dropped before being fully consumed, it
+    /// drops the remaining key-value pairs.
+    pub fn drain(&mut self) -> Drain<'_, K, V, INLINE> {
+        self.finish_resize();
+        let shrink_to = self.auto_shrink.then(|| (self.min_slots, self.new_salt()));
+        Drain {
+            table: &mut self.table,
+            index: 0,
+            shrink_to,
+        }
+    }
+}
+
+impl<K, V, S, const INLINE: usize> HashMap<K, V, S, INLINE> {
+    /// Returns a reference to the map's [`BuildHasher`].
+    pub fn hasher(&self) -> &S {
+        &self.hash_builder
+    }
+
+    /// Returns the number of elements in the map.
+    pub fn len(&self) -> usize {
+        self.table.size + self.old.size
+    }
+
+    /// Returns `true` if the map contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of elements the map can hold without resizing.
+    pub fn capacity(&self) -> usize {
+        max_load(self.table.slots.len(), self.load_factor)
+    }
+
+    /// Returns the load factor of the map, in percent.
+    pub fn load_factor_percent(&self) -> u8 {
+        self.load_factor
+    }
+
+    /// Returns `true` if the table is stored in the map itself, see
+    /// [`with_hasher_inline`].
+    ///
+    /// [`with_hasher_inline`]: HashMap::with_hasher_inline
+    pub fn is_inline(&self) -> bool {
+        self.table.is_inline()
+    }
+
+    /// Returns `true` if an incremental resize is in progress.
+    pub fn is_resizing(&self) -> bool {
+        !self.old.slots.is_empty()
+    }
+
+    /// Completes any incremental resize in progress, so that later
+    /// operations do not pay for it.
+    pub fn finish_resize(&mut self) {
+        self.migrate(usize::MAX);
+    }
+
+    /// Moves the entries of up to `slots` slots of the old table into the
+    /// new one, and frees the old table once it is empty.
+    fn migrate(&mut self, slots: usize) {
+        if !self.is_resizing() {
+            return;
+        }
+        let end = self
+            .migrated
+            .saturating_add(slots)
+            .min(self.old.slots.len());
+        for idx in self.migrated..end {
+            if let Slot::Full(..) = self.old.slots[idx] {
+                let bucket = self.old.take_bucket(idx);
+                self.table.insert_unique(bucket);
+            }
+        }
+        self.migrated = end;
+        if self.old.size == 0 {
+            self.old = RawTable::empty();
+            self.migrated = 0;
+        }
+    }
+
+    /// Moves every entry into the new, empty `table` at once. Keys are placed
+    /// by their cached hashes and never hashed again.
+    fn rehash(&mut self, table: RawTable<K, V, INLINE>) {
+        let old = core::mem::replace(&mut self.table, table);
+        for slot in old.slots {
+            if let Slot::Full(b) = slot {
+                self.table.insert_unique(b);
+            }
+        }
+    }
+
+    /// Calls `f` with the probe length of every entry, see [`BucketStats`].
+    fn for_each_probe_len(&self, mut f: impl FnMut(usize)) {
+        for table in [&self.old, &self.table] {
+            for (idx, slot) in table.slots.iter().enumerate() {
+                if let Slot::Full(b) = slot {
+                    f(table.probe_len(idx, b.hash));
+                }
+            }
+        }
+    }
+
+    /// Computes occupancy and probe-length statistics of the table in a
+    /// single pass over its slots, without hashing any key.
+    pub fn bucket_stats(&self) -> BucketStats {
+        let mut stats = BucketStats {
+            buckets: self.table.slots.len() + self.old.slots.len(),
+            occupied: self.len(),
+            ..BucketStats::default()
+        };
+        let mut total = 0;
+        self.for_each_probe_len(|len| {
+            stats.max_chain = stats.max_chain.max(len);
+            total += len;
+        });
+        stats.empty = stats.buckets - stats.occupied - self.table.deleted - self.old.deleted;
+        if stats.occupied > 0 {
+            stats.avg_chain_x100 = total * 100 / stats.occupied;
+        }
+        stats
+    }
+
+    /// Logs a histogram of probe lengths, to spot a poorly mixing hasher.
+    ///
+    /// Only available in debug builds.
+    #[cfg(debug_assertions)]
+    pub fn dump_distribution(&self) {
+        const ROWS: usize = 8;
+        let mut counts = [0usize; ROWS];
+        self.for_each_probe_len(|len| counts[len.min(ROWS) - 1] += 1);
+        let stats = self.bucket_stats();
+        axlog::info!(
+            "hashmap: {} of {} buckets occupied, {} empty, max chain {}, avg chain {}.{:02}",
+            stats.occupied,
+            stats.buckets,
+            stats.empty,
+            stats.max_chain,
+            stats.avg_chain_x100 / 100,
+            stats.avg_chain_x100 % 100,
+        );
+        for (i, &count) in counts.iter().enumerate() {
+            let more = if i == ROWS - 1 { "+" } else { " " };
+            axlog::info!("hashmap:   chain {}{}: {}", i + 1, more, count);
+        }
+    }
+
+    /// An iterator over the buckets of the current table, in slot order.
+    ///
+    /// The bucket of a slot holds the entries whose probe sequence starts
+    /// there. They sit in the run of slots that follows it, interleaved with
+    /// entries of other buckets. During an incremental resize, the entries
+    /// left in the old table are not visited; call [`finish_resize`] first
+    /// to see them all.
+    ///
+    /// [`finish_resize`]: HashMap::finish_resize
+    #[cfg(feature = "debug-validate")]
+    pub fn buckets(&self) -> impl Iterator<Item = BucketView<'_, K, V, INLINE>> {
+        let table = &self.table;
+        (0..table.slots.len()).map(move |index| BucketView { table, index })
+    }
+
+    /// Returns the heap bytes held by the tables, computed from their
+    /// capacities without visiting any slot.
+    ///
+    /// Heap memory owned by keys and values is not included, see
+    /// [`memory_footprint_with`].
+    ///
+    /// [`memory_footprint_with`]: HashMap::memory_footprint_with
+    pub fn memory_footprint(&self) -> MapFootprint {
+        self.memory_footprint_with(|_, _| 0)
+    }
+
+    /// Like [`memory_footprint`], adding up in `entry_bytes` the heap bytes
+    /// that `f` reports as owned by each key and value.
+    ///
+    /// [`memory_footprint`]: HashMap::memory_footprint
+    pub fn memory_footprint_with<F: FnMut(&K, &V) -> usize>(&self, mut f: F) -> MapFootprint {
+        let slot = core::mem::size_of::<Slot<K, V>>();
+        let (mut capacity, mut spare) = (0, 0);
+        for table in [&self.table, &self.old] {
+            // Inline slots live in the map itself, not on the heap.
+            if !table.is_inline() {
+                capacity += table.slots.heap_capacity();
+                spare += table.slots.heap_capacity() - table.size;
+            }
+        }
+        MapFootprint {
+            bucket_table_bytes: capacity * slot,
+            entry_bytes: self.iter().map(|(k, v)| f(k, v)).sum(),
+            spare_capacity_bytes: spare * slot,
+        }
+    }
+
+    /// Returns clones of all key-value pairs, sorted by key.
+    ///
+    /// Unlike the iteration order, the result depends only on the contents
+    /// of the map and not on its seed or history. It can be persisted and
+    /// loaded back with [`from_sorted_pairs`].
+    ///
+    /// [`from_sorted_pairs`]: HashMap::from_sorted_pairs
+    pub fn to_sorted_vec(&self) -> Vec<(K, V)>
+    where
+        K: Ord + Clone,
+        V: Clone,
+    {
+        let mut pairs: Vec<_> = self.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        pairs.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        pairs
+    }
+
+    /// An iterator visiting all key-value pairs in arbitrary order.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            inner: self.old.slots.iter().chain(self.table.slots.iter()),
+            remaining: self.len(),
+        }
+    }
+
+    /// An iterator visiting all key-value pairs in arbitrary order, with
+    /// mutable references to the values.
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut {
+            remaining: self.len(),
+            inner: self.old.slots.iter_mut().chain(self.table.slots.iter_mut()),
+        }
+    }
+
+    /// An iterator visiting all keys in arbitrary order.
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys { inner: self.iter() }
+    }
+
+    /// An iterator visiting all values in arbitrary order.
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values { inner: self.iter() }
+    }
+
+    /// An iterator visiting all values mutably in arbitrary order.
+    pub fn values_mut(&mut self) -> ValuesMut<'_, K, V> {
+        ValuesMut {
+            inner: self.iter_mut(),
+        }
+    }
+
+    /// Clears the map, removing all key-value pairs. Keeps the allocated
+    /// table for reuse.
+    pub fn clear(&mut self) {
+        self.old = RawTable::empty();
+        self.migrated = 0;
+        self.table.clear();
+    }
+
+    /// Creates an iterator which uses a closure to determine if an element
+    /// should be removed.
+    ///
+    /// If the closure returns `true`, the element is removed from the map and
+    /// yielded. If it returns `false`, the element remains in the map and
+    /// will not be yielded. Unlike [`drain`], dropping the iterator early
+    /// keeps all the elements it has not visited yet.
+    ///
+    /// [`drain`]: HashMap::drain
+    pub fn extract_if<F: FnMut(&K, &mut V) -> bool>(
+        &mut self,
+        pred: F,
+    ) -> ExtractIf<'_, K, V, F, INLINE> {
+        ExtractIf {
+            old: &mut self.old,
+            table: &mut self.table,
+            index: 0,
+            pred,
+        }
+    }
+}
+
+impl<K: Clone, V: Clone, S: Clone, const INLINE: usize> Clone for HashMap<K, V, S, INLINE> {
+    fn clone(&self) -> Self {
+        // Keep the hash state, so that every entry stays in the same slot.
+        Self {
+            table: self.table.clone(),
+            old: self.old.clone(),
+            migrated: self.migrated,
+            load_factor: self.load_factor,
+            auto_shrink: self.auto_shrink,
+            min_slots: self.min_slots,
+            salts: self.salts,
+            random_salts: self.random_salts,
+            hash_builder: self.hash_builder.clone(),
+        }
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug, S, const INLINE: usize> fmt::Debug for HashMap<K, V, S, INLINE> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K: Eq + Hash, V: PartialEq, S: BuildHasher, const INLINE: usize> PartialEq
+    for HashMap<K, V, S, INLINE>
+{
+    fn eq(&self, other: &Self) -> bool {
+        // The two maps may use different salts, so compare by lookup rather
+        // than by bucket layout.
+        self.len() == other.len()
+            && self
+                .iter()
+                .all(|(k, v)| other.get(k).map_or(false, |ov| *v == *ov))
+    }
+}
+
+impl<K: Eq + Hash, V: Eq, S: BuildHasher, const INLINE: usize> Eq for HashMap<K, V, S, INLINE> {}
+
+impl<K, V, S: Default, const INLINE: usize> Default for HashMap<K, V, S, INLINE> {
+    fn default() -> Self {
+        Self::with_parts(0, DEFAULT_LOAD_FACTOR, S::default())
+    }
+}
+
+/// Returns a reference to the value corresponding to the supplied key.
+///
+/// `IndexMut` is deliberately not implemented: indexing cannot insert a
+/// missing key, so use [`HashMap::get_mut`] or [`HashMap::entry`] to modify
+/// values in place.
+///
+/// # Panics
+///
+/// Panics if the key is not present in the map.
+impl<K, Q, V, S, const INLINE: usize> Index<&Q> for HashMap<K, V, S, INLINE>
+where
+    K: Eq + Hash + Borrow<Q>,
+    Q: ?Sized + Hash + Eq,
+    S: BuildHasher,
+{
+    type Output = V;
+
+    fn index(&self, key: &Q) -> &V {
+        self.get(key).expect("key not found in HashMap")
+    }
+}
+
+impl<K, V, S, const INLINE: usize> FromIterator<(K, V)> for HashMap<K, V, S, INLINE>
+where
+    K: Eq + Hash,
+    S: BuildHasher + Default,
+{
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
+        let iter = iter.into_iter();
+        // Make room for the expected number of entries, so that collecting
+        // does not resize repeatedly.
+        let (lower, _) = iter.size_hint();
+        let mut map = HashMap::with_parts(lower, DEFAULT_LOAD_FACTOR, S::default());
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K: Eq + Hash, V, const N: usize> From<[(K, V); N]> for HashMap<K, V> {
+    /// Creates a map from an array of key-value pairs, sizing the table once
+    /// for all of them. For duplicate keys, the last pair wins.
+    fn from(pairs: [(K, V); N]) -> Self {
+        pairs.into_iter().collect()
+    }
+}
+
+impl<K: Eq + Hash, V, S: BuildHasher, const INLINE: usize> Extend<(K, V)>
+    for HashMap<K, V, S, INLINE>
+{
+    fn extend<T: IntoIterator<Item = (K, V)>>(&mut self, iter: T) {
+        for (k, v) in iter {
+            self.insert(k, v);
+        }
+    }
+}
+
+impl<'a, K, V, S, const INLINE: usize> Extend<(&'a K, &'a V)> for HashMap<K, V, S, INLINE>
+where
+    K: Eq + Hash + Copy,
+    V: Copy,
+    S: BuildHasher,
+{
+    fn extend<T: IntoIterator<Item = (&'a K, &'a V)>>(&mut self, iter: T) {
+        for (k, v) in iter {
+            self.insert(*k, *v);
+        }
+    }
+}
+
+impl<K, V, S, const INLINE: usize> IntoIterator for HashMap<K, V, S, INLINE> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V, INLINE>;
+
+    /// Creates a consuming iterator visiting all the key-value pairs in
+    /// arbitrary order. The map cannot be used after calling this.
+    fn into_iter(self) -> IntoIter<K, V, INLINE> {
+        IntoIter {
+            remaining: self.len(),
+            inner: self.old.slots.into_iter().chain(self.table.slots),
+        }
+    }
+}
+
+impl<'a, K, V, S, const INLINE: usize> IntoIterator for &'a HashMap<K, V, S, INLINE> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+impl<'a, K, V, S, const INLINE: usize> IntoIterator for &'a mut HashMap<K, V, S, INLINE> {
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+
+    fn into_iter(self) -> IterMut<'a, K, V> {
+        self.iter_mut()
+    }
+}
+
+/// An iterator over the entries of a [`HashMap`].
+///
+/// This is created by the [`iter`] method on [`HashMap`].
+///
+/// [`iter`]: HashMap::iter
+pub struct Iter<'a, K, V> {
+    inner: SlotsIter<'a, K, V>,
+    remaining: usize,
+}
+
+impl<K, V> Clone for Iter<'_, K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            remaining: self.remaining,
+        }
+    }
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        if self.remaining == 0 {
+            return None;
+        }
+        for slot in &mut self.inner {
+            if let Slot::Full(b) = slot {
+                self.remaining -= 1;
+                return Some((&b.key, &b.value));
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+
+    fn count(self) -> usize {
+        self.remaining
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for Iter<'a, K, V> {
+    fn next_back(&mut self) -> Option<(&'a K, &'a V)> {
+        if self.remaining == 0 {
+            return None;
+        }
+        while let Some(slot) = self.inner.next_back() {
+            if let Slot::Full(b) = slot {
+                self.remaining -= 1;
+                return Some((&b.key, &b.value));
+            }
+        }
+        None
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+impl<K, V> FusedIterator for Iter<'_, K, V> {}
+
+/// A mutable iterator over the entries of a [`HashMap`].
+///
+/// This is created by the [`iter_mut`] method on [`HashMap`].
+///
+/// [`iter_mut`]: HashMap::iter_mut
+pub struct IterMut<'a, K, V> {
+    inner: SlotsIterMut<'a, K, V>,
+    remaining: usize,
+}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a mut V)> {
+        if self.remaining == 0 {
+            return None;
+        }
+        for slot in &mut self.inner {
+            if let Slot::Full(b) = slot {
+                self.remaining -= 1;
+                return Some((&b.key, &mut b.value));
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+
+    fn count(self) -> usize {
+        self.remaining
+    }
+}
+
+impl<K, V> ExactSizeIterator for IterMut<'_, K, V> {}
+impl<K, V> FusedIterator for IterMut<'_, K, V> {}
+
+/// An owning iterator over the entries of a [`HashMap`].
+///
+/// This is created by the [`into_iter`] method on [`HashMap`] (provided by
+/// the [`IntoIterator`] trait).
+///
+/// [`into_iter`]: IntoIterator::into_iter
+pub struct IntoIter<K, V, const INLINE: usize = 0> {
+    inner: SlotsIntoIter<K, V, INLINE>,
+    remaining: usize,
+}
+
+impl<K, V, const INLINE: usize> Iterator for IntoIter<K, V, INLINE> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        if self.remaining == 0 {
+            return None;
+        }
+        for slot in &mut self.inner {
+            if let Slot::Full(b) = slot {
+                self.remaining -= 1;
+                return Some((b.key, b.value));
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V, const INLINE: usize> ExactSizeIterator for IntoIter<K, V, INLINE> {}
+impl<K, V, const INLINE: usize> FusedIterator for IntoIter<K, V, INLINE> {}
+
+/// A draining iterator over the entries of a [`HashMap`].
+///
+/// This is created by the [`drain`] method on [`HashMap`].
+///
+/// [`drain`]: HashMap::drain
+pub struct Drain<'a, K, V, const INLINE: usize = 0> {
+    table: &'a mut RawTable<K, V, INLINE>,
+    index: usize,
+    /// The table size to shrink to once drained and its new salt, if
+    /// automatic shrinking is enabled.
+    shrink_to: Option<(usize, u64)>,
+}
+
+impl<K, V, const INLINE: usize> Iterator for Drain<'_, K, V, INLINE> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        while self.table.size > 0 && self.index < self.table.slots.len() {
+            let idx = self.index;
+            self.index += 1;
+            if let Slot::Full(..) = self.table.slots[idx] {
+                // Leave a tombstone, so that the map stays consistent even if
+                // this iterator is leaked.
+                return Some(self.table.take(idx));
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.table.size, Some(self.table.size))
+    }
+}
+
+impl<K, V, const INLINE: usize> ExactSizeIterator for Drain<'_, K, V, INLINE> {}
+impl<K, V, const INLINE: usize> FusedIterator for Drain<'_, K, V, INLINE> {}
+
+impl<K, V, const INLINE: usize> Drop for Drain<'_, K, V, INLINE> {
+    fn drop(&mut self) {
+        match self.shrink_to {
+            Some((slots, salt)) if slots < self.table.slots.len() => {
+                *self.table = RawTable::with_slots(slots, salt);
+            }
+            _ => self.table.clear(),
+        }
+    }
+}
+
+/// An iterator which removes and yields the entries of a [`HashMap`] that
+/// match a predicate.
+///
+/// This is created by the [`extract_if`] method on [`HashMap`].
+///
+/// [`extract_if`]: HashMap::extract_if
+pub struct ExtractIf<'a, K, V, F, const INLINE: usize = 0> {
+    old: &'a mut RawTable<K, V, INLINE>,
+    table: &'a mut RawTable<K, V, INLINE>,
+    /// Next slot to visit, counting the slots of `old` first.
+    index: usize,
+    pred: F,
+}
+
+impl<K, V, F, const INLINE: usize> Iterator for ExtractIf<'_, K, V, F, INLINE>
+where
+    F: FnMut(&K, &mut V) -> bool,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        loop {
+            let (table, idx) = match self.index.checked_sub(self.old.slots.len()) {
+                None => (&mut *self.old, self.index),
+                Some(idx) if idx < self.table.slots.len() => (&mut *self.table, idx),
+                Some(_) => return None,
+            };
+            self.index += 1;
+            if let Slot::Full(b) = &mut table.slots[idx] {
+                if (self.pred)(&b.key, &mut b.value) {
+                    // Leave a tombstone, so that the entries not visited yet
+                    // can still be found.
+                    return Some(table.take(idx));
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.old.size + self.table.size))
+    }
+}
+
+impl<K, V, F, const INLINE: usize> FusedIterator for ExtractIf<'_, K, V, F, INLINE> where
+    F: FnMut(&K, &mut V) -> bool
+{
+}
+
+/// An iterator over the keys of a [`HashMap`].
+///
+/// This is created by the [`keys`] method on [`HashMap`].
+///
+/// [`keys`]: HashMap::keys
+pub struct Keys<'a, K, V> {
+    inner: Iter<'a, K, V>,
+}
+
+impl<K, V> Clone for Keys<'_, K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<'a, K, V> Iterator for Keys<'a, K, V> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<&'a K> {
+        self.inner.next().map(|(k, _)| k)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+
+    fn count(self) -> usize {
+        self.inner.count()
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for Keys<'a, K, V> {
+    fn next_back(&mut self) -> Option<&'a K> {
+        self.inner.next_back().map(|(k, _)| k)
+    }
+}
+
+impl<K, V> ExactSizeIterator for Keys<'_, K, V> {}
+impl<K, V> FusedIterator for Keys<'_, K, V> {}
+
+/// An iterator over the values of a [`HashMap`].
+///
+/// This is created by the [`values`] method on [`HashMap`].
+///
+/// [`values`]: HashMap::values
+pub struct Values<'a, K, V> {
+    inner: Iter<'a, K, V>,
+}
+
+impl<K, V> Clone for Values<'_, K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<'a, K, V> Iterator for Values<'a, K, V> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<&'a V> {
+        self.inner.next().map(|(_, v)| v)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+
+    fn count(self) -> usize {
+        self.inner.count()
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for Values<'a, K, V> {
+    fn next_back(&mut self) -> Option<&'a V> {
+        self.inner.next_back().map(|(_, v)| v)
+    }
+}
+
+impl<K, V> ExactSizeIterator for Values<'_, K, V> {}
+impl<K, V> FusedIterator for Values<'_, K, V> {}
+
+/// A mutable iterator over the values of a [`HashMap`].
+///
+/// This is created by the [`values_mut`] method on [`HashMap`].
+///
+/// [`values_mut`]: HashMap::values_mut
+pub struct ValuesMut<'a, K, V> {
+    inner: IterMut<'a, K, V>,
+}
+
+impl<'a, K, V> Iterator for ValuesMut<'a, K, V> {
+    type Item = &'a mut V;
+
+    fn next(&mut self) -> Option<&'a mut V> {
+        self.inner.next().map(|(_, v)| v)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+
+    fn count(self) -> usize {
+        self.inner.count()
+    }
+}
+
+impl<K, V> ExactSizeIterator for ValuesMut<'_, K, V> {}
+impl<K, V> FusedIterator for ValuesMut<'_, K, V> {}
+
+/// A bucket of a [`HashMap`], as visited by [`HashMap::buckets`].
+#[cfg(feature = "debug-validate")]
+pub struct BucketView<'a, K, V, const INLINE: usize = 0> {
+    table: &'a RawTable<K, V, INLINE>,
+    index: usize,
+}
+
+#[cfg(feature = "debug-validate")]
+impl<'a, K, V, const INLINE: usize> BucketView<'a, K, V, INLINE> {
+    /// Returns the slot index of the bucket.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the number of entries in the bucket.
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// Returns `true` if the bucket holds no entry.
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    /// An iterator over the entries of the bucket, in probe order.
+    pub fn iter(&self) -> impl Iterator<Item = (&'a K, &'a V)> + '_ {
+        let table = self.table;
+        // The run of slots up to the next empty one, at most the whole table.
+        (0..table.slots.len())
+            .map(move |i| &table.slots[(self.index + i) & table.mask])
+            .take_while(|slot| !matches!(slot, Slot::Empty))
+            .filter_map(move |slot| match slot {
+                Slot::Full(b) if table.home(b.hash) == self.index => Some((&b.key, &b.value)),
+                _ => None,
+            })
+    }
+}
+
+/// A view into a single entry in a map, which may either be vacant or
+/// occupied.
+///
+/// This is constructed from the [`entry`] method on [`HashMap`].
+///
+/// [`entry`]: HashMap::entry
+pub enum Entry<'a, K, V, const INLINE: usize = 0> {
+    /// An occupied entry.
+    Occupied(OccupiedEntry<'a, K, V, INLINE>),
+    /// A vacant entry.
+    Vacant(VacantEntry<'a, K, V, INLINE>),
+}
+
+/// A view into an occupied entry in a [`HashMap`].
+pub struct OccupiedEntry<'a, K, V, const INLINE: usize = 0> {
+    table: &'a mut RawTable<K, V, INLINE>,
+    index: usize,
+    /// The key the entry was looked up with, equal to the stored one.
+    key: K,
+}
+
+/// A view into a vacant entry in a [`HashMap`].
+pub struct VacantEntry<'a, K, V, const INLINE: usize = 0> {
+    table: &'a mut RawTable<K, V, INLINE>,
+    index: usize,
+    hash: u64,
+    key: K,
+}
+
+impl<'a, K, V, const INLINE: usize> Entry<'a, K, V, INLINE> {
+    /// Returns a reference to this entry's key.
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    /// Ensures a value is in the entry by inserting the default if empty, and
+    /// returns a mutable reference to the value in the entry.
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default),
+        }
+    }
+
+    /// Ensures a value is in the entry by inserting the result of the default
+    /// function if empty, and returns a mutable reference to the value in the
+    /// entry.
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    /// Provides in-place mutable access to an occupied entry before any
+    /// potential inserts into the map.
+    pub fn and_modify<F: FnOnce(&mut V)>(mut self, f: F) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+}
+
+impl<'a, K, V: Default, const INLINE: usize> Entry<'a, K, V, INLINE> {
+    /// Ensures a value is in the entry by inserting the default value if
+    /// empty, and returns a mutable reference to the value in the entry.
+    pub fn or_default(self) -> &'a mut V {
+        self.or_insert_with(V::default)
+    }
+}
+
+impl<'a, K, V, const INLINE: usize> OccupiedEntry<'a, K, V, INLINE> {
+    /// Gets a reference to the key stored in the map, rather than the one
+    /// used to look up the entry.
+    pub fn key(&self) -> &K {
+        self.table.get(self.index).0
+    }
+
+    /// Gets a reference to the value in the entry.
+    pub fn get(&self) -> &V {
+        self.table.get(self.index).1
+    }
+
+    /// Gets a mutable reference to the value in the entry.
+    pub fn get_mut(&mut self) -> &mut V {
+        self.table.get_mut(self.index).1
+    }
+
+    /// Converts the entry into a mutable reference to the value in the entry
+    /// with a lifetime bound to the map itself.
+    pub fn into_mut(self) -> &'a mut V {
+        self.table.get_mut(self.index).1
+    }
+
+    /// Sets the value of the entry, and returns the entry's old value.
+    pub fn insert(&mut self, value: V) -> V {
+        core::mem::replace(self.get_mut(), value)
+    }
+
+    /// Takes the value out of the entry, and returns it.
+    pub fn remove(self) -> V {
+        self.table.take(self.index).1
+    }
+
+    /// Takes the stored key and the value out of the entry, and returns
+    /// them.
+    pub fn remove_entry(self) -> (K, V) {
+        self.table.take(self.index)
+    }
+
+    /// Replaces the stored key with the key the entry was looked up with,
+    /// and returns the old one. The value is left untouched.
+    ///
+    /// The keys are equal, so the entry stays in place; only the stored
+    /// instance changes, e.g. to adopt a newer allocation.
+    pub fn replace_key(self) -> K {
+        core::mem::replace(self.table.key_mut(self.index), self.key)
+    }
+
+    /// Replaces both the stored key, like [`replace_key`], and the value, and
+    /// returns the old ones.
+    ///
+    /// [`replace_key`]: OccupiedEntry::replace_key
+    pub fn replace_entry(mut self, value: V) -> (K, V) {
+        let old = self.insert(value);
+        (self.replace_key(), old)
+    }
+}
+
+impl<'a, K, V, const INLINE: usize> VacantEntry<'a, K, V, INLINE> {
+    /// Gets a reference to the key that would be used when inserting a value
+    /// through the `VacantEntry`.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Takes ownership of the key back, without inserting anything.
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    /// Sets the value of the entry with the `VacantEntry`'s key, and returns
+    /// a mutable reference to it.
+    pub fn insert(self, value: V) -> &'a mut V {
+        self.table.put(self.index, self.hash, self.key, value)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use core::alloc::{GlobalAlloc, Layout};
+    use core::cell::Cell;
+
+    /// Counts the heap allocations and deallocations made by each thread, so
+    /// that tests can check how often the map allocates, and can make them
+    /// fail.
+    struct CountingAlloc;
+
+    ::std::thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+        static DEALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+        static LIVE_BYTES: Cell<usize> = const { Cell::new(0) };
+        static FAIL_ALLOCATIONS: Cell<bool> = const { Cell::new(false) };
+    }
+
+    unsafe impl GlobalAlloc for CountingAlloc {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            if FAIL_ALLOCATIONS.try_with(Cell::get).unwrap_or(false) {
+                return core::ptr::null_mut();
+            }
+            let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+            let _ = LIVE_BYTES.try_with(|n| n.set(n.get().wrapping_add(layout.size())));
+            ::std::alloc::System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            let _ = DEALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+            let _ = LIVE_BYTES.try_with(|n| n.set(n.get().wrapping_sub(layout.size())));
+            ::std::alloc::System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static GLOBAL: CountingAlloc = CountingAlloc;
+
+    /// Returns the number of allocations made so far by this thread.
+    fn allocations() -> usize {
+        ALLOCATIONS.with(|n| n.get())
+    }
+
+    /// Returns the number of deallocations made so far by this thread.
+    fn deallocations() -> usize {
+        DEALLOCATIONS.with(|n| n.get())
+    }
+
+    /// Returns the number of heap bytes allocated by this thread and not
+    /// freed yet, modulo blocks moved to or from other threads. Only
+    /// differences are meaningful.
+    fn live_bytes() -> usize {
+        LIVE_BYTES.with(|n| n.get())
+    }
+
+    /// Runs `f` with every allocation of this thread failing.
+    fn without_memory<R>(f: impl FnOnce() -> R) -> R {
+        FAIL_ALLOCATIONS.with(|fail| fail.set(true));
+        let result = f();
+        FAIL_ALLOCATIONS.with(|fail| fail.set(false));
+        result
+    }
+
+    #[test]
+    fn test_get() {
+        let mut map = HashMap::new();
+        map.insert(1, "one");
+        map.insert(2, "two");
+        assert_eq!(map.get(&1), Some(&"one"));
+        assert_eq!(map.get(&2), Some(&"two"));
+        assert_eq!(map.get(&3), None);
+
+        *map.get_mut(&1).unwrap() = "uno";
+        assert_eq!(map.get(&1), Some(&"uno"));
+        assert!(map.get_mut(&3).is_none());
+    }
+
+    #[test]
+    fn test_get_empty() {
+        let mut map: HashMap<u32, u32> = HashMap::with_capacity(0);
+        assert_eq!(map.get(&0), None);
+        assert!(map.get_mut(&0).is_none());
+    }
+
+    #[test]
+    fn test_get_after_resize() {
+        let mut map = HashMap::new();
+        for i in 0..1000u32 {
+            map.insert(i, i * 10);
+        }
+        for i in 0..1000u32 {
+            assert_eq!(map.get(&i), Some(&(i * 10)));
+        }
+        assert_eq!(map.get(&1000), None);
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut map = HashMap::new();
+        for i in 0..100u32 {
+            map.insert(i, i + 1);
+        }
+        for i in (0..100u32).step_by(2) {
+            assert_eq!(map.remove(&i), Some(i + 1));
+        }
+        assert_eq!(map.remove(&0), None);
+        assert_eq!(map.remove(&100), None);
+        assert_eq!(map.remove_entry(&1), Some((1, 2)));
+        assert_eq!(map.remove_entry(&1), None);
+
+        let mut survivors: Vec<_> = map.iter().map(|(k, v)| (*k, *v)).collect();
+        survivors.sort();
+        let expected: Vec<_> = (3..100u32).step_by(2).map(|i| (i, i + 1)).collect();
+        assert_eq!(survivors, expected);
+        assert_eq!(map.len(), expected.len());
+    }
+
+    #[test]
+    fn test_len() {
+        let mut map = HashMap::new();
+        assert!(map.is_empty());
+        assert!(!map.contains_key(&"a"));
+
+        map.insert("a", 1);
+        map.insert("b", 2);
+        assert_eq!(map.len(), 2);
+        map.insert("a", 3);
+        assert_eq!(map.len(), 2);
+        assert!(map.contains_key(&"a"));
+
+        map.remove(&"a");
+        assert_eq!(map.len(), 1);
+        assert!(!map.contains_key(&"a"));
+        map.remove(&"a");
+        assert_eq!(map.len(), 1);
+        map.remove(&"b");
+        assert_eq!(map.len(), 0);
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn test_insert_returns_old() {
+        let mut map = HashMap::new();
+        assert_eq!(map.insert(7, "a"), None);
+        assert_eq!(map.insert(7, "b"), Some("a"));
+        assert_eq!(map.get(&7), Some(&"b"));
+
+        for i in 100..200 {
+            assert_eq!(map.insert(i, "x"), None);
+        }
+        assert_eq!(map.insert(7, "c"), Some("b"));
+        assert_eq!(map.insert(150, "y"), Some("x"));
+        assert_eq!(map.len(), 101);
+    }
+
+    #[test]
+    fn test_entry_word_count() {
+        let text = "the quick brown fox jumps over the lazy dog the end";
+        let mut map = HashMap::new();
+        for word in text.split(' ') {
+            *map.entry(word).or_insert(0) += 1;
+        }
+        assert_eq!(map.len(), 9);
+        assert_eq!(map.get(&"the"), Some(&3));
+        assert_eq!(map.get(&"fox"), Some(&1));
+        assert_eq!(map.get(&"cat"), None);
+    }
+
+    #[test]
+    fn test_entry_adapters() {
+        let mut map: HashMap<u32, u32> = HashMap::new();
+        map.entry(1).and_modify(|v| *v += 1).or_insert(10);
+        assert_eq!(map.get(&1), Some(&10));
+        map.entry(1).and_modify(|v| *v += 1).or_insert(10);
+        assert_eq!(map.get(&1), Some(&11));
+        assert_eq!(*map.entry(2).or_default(), 0);
+        assert_eq!(*map.entry(3).or_insert_with(|| 42), 42);
+
+        match map.entry(1) {
+            Entry::Occupied(mut entry) => {
+                assert_eq!(*entry.get(), 11);
+                assert_eq!(entry.insert(12), 11);
+                assert_eq!(entry.remove(), 12);
+            }
+            Entry::Vacant(_) => panic!("expected an occupied entry"),
+        }
+        assert!(!map.contains_key(&1));
+        assert_eq!(map.len(), 2);
+
+        // Filling vacant entries across the resize threshold.
+        for i in 100..1000 {
+            match map.entry(i) {
+                Entry::Vacant(entry) => *entry.insert(i) += 1,
+                Entry::Occupied(_) => panic!("expected a vacant entry"),
+            }
+        }
+        for i in 100..1000 {
+            assert_eq!(map.get(&i), Some(&(i + 1)));
+        }
+        assert_eq!(map.len(), 902);
+    }
+
+    #[test]
+    fn test_iter_mut() {
+        let mut map = HashMap::new();
+        for i in 0..100u32 {
+            map.insert(i, i);
+        }
+        for (k, v) in map.iter_mut() {
+            assert_eq!(*k, *v);
+            *v *= 2;
+        }
+        for i in 0..100u32 {
+            assert_eq!(map.get(&i), Some(&(i * 2)));
+        }
+        for v in map.values_mut() {
+            *v += 1;
+        }
+        for i in 0..100u32 {
+            assert_eq!(map.get(&i), Some(&(i * 2 + 1)));
+        }
+    }
+
+    #[test]
+    fn test_keys_values() {
+        let mut map = HashMap::new();
+        let names = ["usr", "bin", "etc", "dev", "proc", "sys", "tmp", "var"];
+        for (i, name) in names.iter().enumerate() {
+            map.insert(*name, i);
+        }
+
+        let keys = map.keys();
+        assert_eq!(keys.size_hint(), (names.len(), Some(names.len())));
+        let mut keys: Vec<_> = keys.copied().collect();
+        keys.sort();
+        let mut expected = names.to_vec();
+        expected.sort();
+        assert_eq!(keys, expected);
+
+        let mut values = map.values();
+        values.next();
+        assert_eq!(values.size_hint(), (names.len() - 1, Some(names.len() - 1)));
+        let mut values: Vec<_> = map.values().copied().collect();
+        values.sort();
+        assert_eq!(values, (0..names.len()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_into_iter() {
+        let mut map = HashMap::new();
+        for i in 0..100u32 {
+            map.insert(i, i * 3);
+        }
+
+        let mut count = 0;
+        for (k, v) in &map {
+            assert_eq!(*v, *k * 3);
+            count += 1;
+        }
+        assert_eq!(count, 100);
+
+        for (_, v) in &mut map {
+            *v += 1;
+        }
+
+        let iter = map.into_iter();
+        assert_eq!(iter.len(), 100);
+        let mut seen = [false; 100];
+        for (k, v) in iter {
+            assert_eq!(v, k * 3 + 1);
+            assert!(!seen[k as usize]);
+            seen[k as usize] = true;
+        }
+        assert!(seen.iter().all(|&s| s));
+    }
+
+    #[test]
+    fn test_from_iter_extend() {
+        let map: HashMap<u32, u32> = (0..1000).map(|i| (i, i * i)).collect();
+        assert_eq!(map.len(), 1000);
+        for i in 0..1000 {
+            assert_eq!(map.get(&i), Some(&(i * i)));
+        }
+
+        let map: HashMap<u32, u32> = (0..10).filter(|i| i % 2 == 0).map(|i| (i, i)).collect();
+        assert_eq!(map.len(), 5);
+
+        let mut map: HashMap<u32, &str> = [(1, "a"), (2, "b"), (1, "c")].into_iter().collect();
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get(&1), Some(&"c"));
+
+        map.extend([(2, "x"), (3, "y")]);
+        assert_eq!(map.len(), 3);
+        assert_eq!(map.get(&2), Some(&"x"));
+        assert_eq!(map.get(&3), Some(&"y"));
+
+        let other = [(3, "z"), (4, "w")];
+        map.extend(other.iter().map(|(k, v)| (k, v)));
+        assert_eq!(map.len(), 4);
+        assert_eq!(map.get(&3), Some(&"z"));
+        assert_eq!(map.get(&4), Some(&"w"));
+    }
+
+    #[test]
+    fn test_clone_eq_debug() {
+        let mut map = HashMap::default();
+        for i in 0..100u32 {
+            map.insert(i, i + 1);
+        }
+        let cloned = map.clone();
+        assert_eq!(cloned, map);
+        assert_eq!(cloned.get(&50), Some(&51));
+
+        // Same contents, different salt and insertion order.
+        let mut other = HashMap::with_capacity(64);
+        for i in (0..100u32).rev() {
+            other.insert(i, i + 1);
+        }
+        assert_eq!(other, map);
+
+        other.insert(0, 0);
+        assert_ne!(other, map);
+        other.insert(0, 1);
+        other.insert(100, 101);
+        assert_ne!(other, map);
+
+        let mut small = HashMap::new();
+        small.insert("k", 1);
+        assert_eq!(format!("{:?}", small), "{\"k\": 1}");
+        assert_eq!(format!("{:?}", HashMap::<u32, u32>::new()), "{}");
+    }
+
+    #[test]
+    fn test_index() {
+        let mut map = HashMap::new();
+        map.insert("a", 1);
+        map.insert("b", 2);
+        assert_eq!(map[&"a"], 1);
+        assert_eq!(map[&"b"], 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "key not found")]
+    fn test_index_missing() {
+        let mut map = HashMap::new();
+        map.insert("a", 1);
+        let _ = map[&"b"];
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut map: HashMap<u32, u32> = (0..100).map(|i| (i, i)).collect();
+        let buckets = map.table.slots.len();
+        map.clear();
+        assert!(map.is_empty());
+        assert_eq!(map.iter().count(), 0);
+        assert_eq!(map.get(&1), None);
+        assert_eq!(map.table.slots.len(), buckets);
+        map.insert(1, 2);
+        assert_eq!(map.get(&1), Some(&2));
+    }
+
+    #[test]
+    fn test_drain() {
+        use alloc::rc::Rc;
+
+        let token = Rc::new(());
+        let mut map = HashMap::new();
+        for i in 0..100u32 {
+            map.insert(i, token.clone());
+        }
+        assert_eq!(Rc::strong_count(&token), 101);
+
+        let mut drain = map.drain();
+        assert_eq!(drain.len(), 100);
+        let yielded: Vec<_> = drain.by_ref().take(50).collect();
+        assert_eq!(drain.len(), 50);
+        drop(drain);
+
+        assert!(map.is_empty());
+        assert_eq!(map.iter().count(), 0);
+        // Only the yielded values are still alive; the rest were dropped.
+        assert_eq!(Rc::strong_count(&token), 51);
+        drop(yielded);
+        assert_eq!(Rc::strong_count(&token), 1);
+
+        map.insert(7, token.clone());
+        let all: Vec<_> = map.drain().map(|(k, _)| k).collect();
+        assert_eq!(all, [7]);
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn test_retain() {
+        let mut map: HashMap<u32, u32> = (0..1000).map(|i| (i, i)).collect();
+        map.retain(|_, v| {
+            *v += 1;
+            *v % 2 == 0
+        });
+        assert_eq!(map.len(), 500);
+        assert_eq!(map.iter().count(), 500);
+        for i in 0..1000 {
+            if i % 2 == 1 {
+                assert_eq!(map.get(&i), Some(&(i + 1)));
+            } else {
+                assert_eq!(map.get(&i), None);
+            }
+        }
+    }
+
+    #[test]
+    fn test_reserve() {
+        let mut map = HashMap::new();
+        assert_eq!(map.capacity(), 0);
+        map.reserve(10_000);
+        assert!(map.capacity() >= 10_000);
+        let buckets = map.table.slots.len();
+        for i in 0..10_000u32 {
+            map.insert(i, i);
+            assert_eq!(map.table.slots.len(), buckets);
+        }
+        assert_eq!(map.len(), 10_000);
+
+        // Already enough room: no change.
+        map.reserve(0);
+        assert_eq!(map.table.slots.len(), buckets);
+    }
+
+    #[test]
+    fn test_shrink_to_fit() {
+        let mut map: HashMap<u32, u32> = (0..1000).map(|i| (i, i)).collect();
+        map.retain(|k, _| *k < 10);
+        map.shrink_to_fit();
+        assert!(map.capacity() >= 10);
+        assert_eq!(map.table.slots.len(), 16);
+        for i in 0..10 {
+            assert_eq!(map.get(&i), Some(&i));
+        }
+
+        map.clear();
+        map.shrink_to_fit();
+        assert_eq!(map.capacity(), 0);
+        assert_eq!(map.get(&1), None);
+        map.insert(1, 1);
+        assert_eq!(map.get(&1), Some(&1));
+    }
+
+    #[test]
+    fn test_borrowed_lookup() {
+        let mut map: HashMap<String, u32> = HashMap::new();
+        map.insert("alpha".to_string(), 1);
+        map.insert("beta".to_string(), 2);
+        assert_eq!(map.get("alpha"), Some(&1));
+        assert_eq!(map["beta"], 2);
+        assert!(map.contains_key("beta"));
+        assert!(!map.contains_key("gamma"));
+        *map.get_mut("alpha").unwrap() += 10;
+        assert_eq!(map.get("alpha"), Some(&11));
+        assert_eq!(map.remove("alpha"), Some(11));
+        assert_eq!(map.remove_entry("beta"), Some(("beta".to_string(), 2)));
+        assert!(map.is_empty());
+
+        let mut map: HashMap<Vec<u8>, &str> = HashMap::new();
+        map.insert(b"key".to_vec(), "value");
+        let probe: &[u8] = b"key";
+        assert_eq!(map.get(probe), Some(&"value"));
+        assert_eq!(map.get(&b"nope"[..]), None);
+    }
+
+    #[test]
+    fn test_with_hasher() {
+        use core::hash::BuildHasherDefault;
+
+        type Fixed = BuildHasherDefault<SimpleHasher>;
+
+        let build = || {
+            let mut map = HashMap::with_hasher(Fixed::default());
+            for i in 0..100u32 {
+                map.insert(i, i);
+            }
+            map
+        };
+        let a = build();
+        let b = build();
+        assert_eq!(a.table.slots.len(), b.table.slots.len());
+        for (x, y) in a.table.slots.iter().zip(b.table.slots.iter()) {
+            match (x, y) {
+                (Slot::Full(x), Slot::Full(y)) => assert_eq!(x.key, y.key),
+                (Slot::Empty, Slot::Empty) => {}
+                _ => panic!("slot layouts differ"),
+            }
+        }
+        assert!(a.iter().eq(b.iter()));
+
+        let mut c: HashMap<u32, u32, Fixed> =
+            HashMap::with_capacity_and_hasher(4, Fixed::default());
+        c.insert(1, 2);
+        assert_eq!(c.get(&1), Some(&2));
+        let d: HashMap<u32, u32, Fixed> = HashMap::default();
+        assert!(d.is_empty());
+        let e: HashMap<u32, u32, Fixed> = (0..10).map(|i| (i, i)).collect();
+        assert_eq!(e.len(), 10);
+    }
+
+    #[test]
+    fn test_simple_build_hasher() {
+        let a = SimpleBuildHasher::with_seed(42);
+        let b = SimpleBuildHasher::with_seed(42);
+        assert_eq!(a.hash_one("page-cache-key"), b.hash_one("page-cache-key"));
+        assert_eq!(a.hash_one(12345u64), b.hash_one(12345u64));
+
+        // Different seeds should place at least some keys differently.
+        let c = SimpleBuildHasher::with_seed(43);
+        let differ = (0..64u32)
+            .filter(|i| a.hash_one(i) % 16 != c.hash_one(i) % 16)
+            .count();
+        assert!(differ > 0);
+
+        let mut x = HashMap::with_hasher(SimpleBuildHasher::with_seed(7));
+        let mut y = HashMap::with_hasher(SimpleBuildHasher::with_seed(7));
+        for i in 0..50u32 {
+            x.insert(i, ());
+            y.insert(i, ());
+        }
+        assert!(x.keys().eq(y.keys()));
+    }
+
+    /// A small xorshift generator, so that the churn tests are reproducible.
+    pub(crate) fn xorshift(state: &mut u64) -> u64 {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        *state
+    }
+
+    #[test]
+    fn test_tombstone_reuse() {
+        let mut map = HashMap::with_hasher(SimpleBuildHasher::with_seed(1));
+        for i in 0..8u32 {
+            map.insert(i, i);
+        }
+        let slots = map.table.slots.len();
+        // Removing and re-inserting the same keys reuses their tombstones
+        // instead of growing the table.
+        for _ in 0..1000 {
+            for i in 0..8u32 {
+                assert_eq!(map.remove(&i), Some(i));
+            }
+            for i in 0..8u32 {
+                assert_eq!(map.insert(i, i), None);
+            }
+        }
+        assert_eq!(map.len(), 8);
+        assert_eq!(map.table.slots.len(), slots);
+        assert!(map.table.deleted * 4 < slots * 3);
+
+        // A removed key is not found, even when others probed past it.
+        map.remove(&3);
+        assert_eq!(map.get(&3), None);
+        for i in (0..8u32).filter(|&i| i != 3) {
+            assert_eq!(map.get(&i), Some(&i));
+        }
+    }
+
+    #[test]
+    fn test_churn_high_load() {
+        let mut map = HashMap::with_hasher(SimpleBuildHasher::with_seed(5));
+        let mut reference = alloc::collections::BTreeMap::new();
+        let mut state = 0x2545_f491_4f6c_dd1d;
+        for round in 0..20_000u32 {
+            // Keep the key space small, so that the table stays close to its
+            // load factor and accumulates tombstones.
+            let key = (xorshift(&mut state) % 96) as u32;
+            if xorshift(&mut state) % 2 == 0 {
+                assert_eq!(map.insert(key, round), reference.insert(key, round));
+            } else {
+                assert_eq!(map.remove(&key), reference.remove(&key));
+            }
+            assert_eq!(map.len(), reference.len());
+            assert!((map.table.size + map.table.deleted) <= map.capacity());
+        }
+        for (k, v) in &reference {
+            assert_eq!(map.get(k), Some(v));
+        }
+        let mut items: Vec<_> = map.into_iter().collect();
+        items.sort();
+        assert!(items.into_iter().eq(reference));
+    }
+
+    #[test]
+    fn test_bytes_used() {
+        let mut map = HashMap::with_hasher(SimpleBuildHasher::with_seed(0));
+        for i in 0..1000u64 {
+            map.insert(i, i);
+        }
+        map.finish_resize();
+        // Once resizing is done, a single flat table. Separate chaining would
+        // need one `Vec` header per bucket plus a heap block per non-empty
+        // bucket on top of the entries themselves (counted here with their
+        // cached hashes too).
+        let entry = core::mem::size_of::<Bucket<u64, u64>>();
+        let slot = core::mem::size_of::<Slot<u64, u64>>();
+        let used = map.memory_footprint().bucket_table_bytes;
+        assert_eq!(used, map.table.slots.len() * slot);
+        let chained =
+            map.table.slots.len() * core::mem::size_of::<Vec<(u64, u64)>>() + map.len() * entry;
+        assert!(used < chained);
+    }
+
+    /// Run with `cargo test --features alloc -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_insert_get_remove() {
+        const N: u64 = 200_000;
+        let start = ::std::time::Instant::now();
+        let mut map = HashMap::with_hasher(SimpleBuildHasher::with_seed(0));
+        for i in 0..N {
+            map.insert(i, i);
+        }
+        for i in 0..N {
+            assert_eq!(map.get(&i), Some(&i));
+        }
+        for i in 0..N {
+            assert_eq!(map.remove(&i), Some(i));
+        }
+        ::std::println!("{} inserts, gets and removes: {:?}", N, start.elapsed());
+    }
+
+    /// Run with `cargo test --features alloc -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_hash_u64() {
+        const N: u64 = 1_000_000;
+        let hasher = SimpleBuildHasher::with_seed(0);
+        let time = |f: &dyn Fn(u64) -> u64| {
+            let start = ::std::time::Instant::now();
+            for i in 0..N {
+                core::hint::black_box(f(core::hint::black_box(i)));
+            }
+            start.elapsed()
+        };
+        let word = time(&|i| hasher.hash_one(i));
+        let bytes = time(&|i| {
+            let mut h = hasher.build_hasher();
+            h.write(&i.to_ne_bytes());
+            h.finish()
+        });
+        ::std::println!("{} u64 hashes: {:?} by word, {:?} by byte", N, word, bytes);
+    }
+
+    /// Folds the results of a fixed pseudo-random sequence of operations
+    /// into a checksum.
+    fn golden_trace() -> u64 {
+        let mut map = HashMap::with_hasher(SimpleBuildHasher::with_seed(9));
+        let mut state = 0x9e37_79b9_7f4a_7c15;
+        let mut trace = 0xcbf2_9ce4_8422_2325u64;
+        let mut record = |x: u64| trace = (trace ^ x).wrapping_mul(0x100_0000_01b3);
+        for round in 0..50_000u64 {
+            let key = xorshift(&mut state) % 512;
+            let result = match xorshift(&mut state) % 3 {
+                0 => map.insert(key, round),
+                1 => map.get(&key).copied(),
+                _ => map.remove(&key),
+            };
+            record(result.map_or(u64::MAX, |v| v));
+            record(map.len() as u64);
+        }
+        let mut items: Vec<_> = map.into_iter().collect();
+        items.sort();
+        for (k, v) in items {
+            record(k);
+            record(v);
+        }
+        trace
+    }
+
+    #[test]
+    fn test_golden_trace() {
+        // Recorded with the previous, modulo-indexed table: the slot layout
+        // changed, the observable behavior must not.
+        assert_eq!(golden_trace(), 16816038721016627941);
+    }
+
+    #[test]
+    #[cfg_attr(feature = "debug-validate", ignore = "validation hashes every key")]
+    fn test_cached_hash() {
+        use core::sync::atomic::{AtomicUsize, Ordering};
+
+        static HASHES: AtomicUsize = AtomicUsize::new(0);
+
+        #[derive(PartialEq, Eq)]
+        struct Counted(u32);
+
+        impl Hash for Counted {
+            fn hash<H: Hasher>(&self, state: &mut H) {
+                HASHES.fetch_add(1, Ordering::Relaxed);
+                self.0.hash(state);
+            }
+        }
+
+        let mut map = HashMap::new();
+        for i in 0..100 {
+            map.insert(Counted(i), i);
+        }
+        // One hash per insert, including those that triggered a resize.
+        assert_eq!(HASHES.load(Ordering::Relaxed), 100);
+
+        map.reserve(10_000);
+        map.shrink_to_fit();
+        assert_eq!(HASHES.load(Ordering::Relaxed), 100);
+        for i in 0..100 {
+            assert_eq!(map.get(&Counted(i)), Some(&i));
+        }
+        assert_eq!(HASHES.load(Ordering::Relaxed), 200);
+    }
+
+    #[test]
+    fn test_incremental_resize() {
+        let mut map = HashMap::with_hasher(SimpleBuildHasher::with_seed(3));
+        let mut reference = alloc::collections::BTreeMap::new();
+        let mut state = 0x1234_5678_9abc_def1;
+        let mut migrations = 0;
+        for round in 0..20_000u32 {
+            let was_resizing = map.is_resizing();
+            let key = (xorshift(&mut state) % 4096) as u32;
+            match xorshift(&mut state) % 4 {
+                0 | 1 => assert_eq!(map.insert(key, round), reference.insert(key, round)),
+                2 => assert_eq!(map.remove(&key), reference.remove(&key)),
+                _ => {
+                    if let Some(v) = map.get_mut(&key) {
+                        *v += 1;
+                    }
+                    if let Some(v) = reference.get_mut(&key) {
+                        *v += 1;
+                    }
+                }
+            }
+            if map.is_resizing() {
+                migrations += !was_resizing as usize;
+                // Both tables are live: lookups, `len` and iteration must
+                // still see every entry exactly once.
+                assert!(map.old.size > 0);
+                assert_eq!(map.len(), reference.len());
+                assert_eq!(map.get(&key), reference.get(&key));
+                if round % 64 == 0 {
+                    let mut items: Vec<_> = map.iter().map(|(k, v)| (*k, *v)).collect();
+                    items.sort();
+                    assert!(items.into_iter().eq(reference.clone()));
+                }
+            }
+        }
+        assert!(migrations > 1);
+        for (k, v) in &reference {
+            assert_eq!(map.get(k), Some(v));
+        }
+
+        // Retain and drain cover both tables too.
+        while !map.is_resizing() {
+            let key = (xorshift(&mut state) % 100_000) as u32;
+            map.insert(key, 0);
+            reference.insert(key, 0);
+        }
+        map.retain(|k, _| k % 2 == 0);
+        reference.retain(|k, _| k % 2 == 0);
+        assert_eq!(map.len(), reference.len());
+        let mut items: Vec<_> = map.drain().collect();
+        items.sort();
+        assert!(items.into_iter().eq(reference));
+        assert!(map.is_empty() && !map.is_resizing());
+    }
+
+    #[test]
+    fn test_finish_resize() {
+        let mut map = HashMap::new();
+        let mut i = 0u32;
+        while !map.is_resizing() {
+            map.insert(i, i);
+            i += 1;
+        }
+        map.finish_resize();
+        assert!(!map.is_resizing());
+        assert_eq!(map.old.slots.heap_capacity(), 0);
+        assert_eq!(map.len(), i as usize);
+        for j in 0..i {
+            assert_eq!(map.get(&j), Some(&j));
+        }
+        // Nothing left to do.
+        map.finish_resize();
+        assert_eq!(map.len(), i as usize);
+    }
+
+    #[test]
+    fn test_new_does_not_allocate() {
+        let before = allocations();
+        {
+            let mut map: HashMap<u32, u32> = HashMap::new();
+            assert_eq!(map.get(&1), None);
+            assert_eq!(map.get_mut(&1), None);
+            assert!(!map.contains_key(&1));
+            assert_eq!(map.remove(&1), None);
+            assert!(map.is_empty());
+            assert_eq!(map.len(), 0);
+            assert_eq!(map.capacity(), 0);
+            assert_eq!(map.iter().count(), 0);
+            assert_eq!(map.iter_mut().count(), 0);
+            assert_eq!(map.keys().count() + map.values().count(), 0);
+            assert_eq!(map.drain().count(), 0);
+            map.retain(|_, _| false);
+            map.clear();
+            map.shrink_to_fit();
+            let other = HashMap::default();
+            assert!(map == other);
+            assert_eq!(map.into_iter().count(), 0);
+        }
+        assert_eq!(allocations(), before);
+
+        let mut map = HashMap::new();
+        map.insert(1, 1);
+        assert_eq!(allocations(), before + 1);
+        assert_eq!(map.get(&1), Some(&1));
+    }
+
+    #[test]
+    fn test_with_capacity() {
+        let mut map = HashMap::with_capacity(0);
+        assert_eq!(map.capacity(), 0);
+        map.insert(1, 1);
+        assert_eq!(map.get(&1), Some(&1));
+
+        for cap in [1, 10, 12, 13, 100, 1000] {
+            let mut map = HashMap::with_capacity(cap);
+            assert!(map.capacity() >= cap);
+            assert!(map.table.slots.len().is_power_of_two());
+            // Every table allocates, so a resize would show up here.
+            let before = allocations();
+            for i in 0..cap {
+                map.insert(i, i);
+            }
+            assert_eq!(allocations(), before, "with_capacity({}) resized", cap);
+            assert!(!map.is_resizing());
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "capacity overflow")]
+    fn test_reserve_overflow_with_tombstones() {
+        let mut map: HashMap<u32, u32> = (0..10).map(|i| (i, i)).collect();
+        for i in 0..5 {
+            map.remove(&i);
+        }
+        map.reserve(usize::MAX - 5);
+    }
+
+    #[test]
+    fn test_try_reserve() {
+        let mut map: HashMap<u32, u32> = (0..100).map(|i| (i, i)).collect();
+        assert!(map.try_reserve(usize::MAX).is_err());
+        assert!(without_memory(|| map.try_reserve(10_000)).is_err());
+        assert_eq!(map.len(), 100);
+        for i in 0..100 {
+            assert_eq!(map.get(&i), Some(&i));
+        }
+
+        // Enough room already: nothing to allocate.
+        let room = map.capacity() - map.len();
+        assert!(without_memory(|| map.try_reserve(room)).is_ok());
+
+        assert!(map.try_reserve(10_000).is_ok());
+        assert!(map.capacity() >= 10_100);
+        let before = allocations();
+        for i in 100..10_100 {
+            map.insert(i, i);
+        }
+        assert_eq!(allocations(), before);
+    }
+
+    #[test]
+    fn test_try_reserve_with_tombstones() {
+        let mut map: HashMap<u32, u32> = (0..10).map(|i| (i, i)).collect();
+        for i in 0..5 {
+            map.remove(&i);
+        }
+        // Counting the tombstones on top must not overflow.
+        assert!(map.try_reserve(usize::MAX - 5).is_err());
+        assert_eq!(map.len(), 5);
+        for i in 5..10 {
+            assert_eq!(map.get(&i), Some(&i));
+        }
+    }
+
+    #[test]
+    fn test_try_insert() {
+        let mut map = HashMap::with_capacity(12);
+        for i in 0..12u32 {
+            assert_eq!(without_memory(|| map.try_insert(i, i)), Ok(None));
+        }
+        // Replacing a value never needs to grow the table.
+        assert_eq!(without_memory(|| map.try_insert(0, 100)), Ok(Some(0)));
+        assert!(without_memory(|| map.try_insert(12, 12)).is_err());
+        assert_eq!(map.len(), 12);
+        assert_eq!(map.get(&12), None);
+        for i in 1..12 {
+            assert_eq!(map.get(&i), Some(&i));
+        }
+
+        assert_eq!(map.try_insert(12, 12), Ok(None));
+        assert_eq!(map.try_insert(0, 0), Ok(Some(100)));
+        assert_eq!(map.len(), 13);
+    }
+
+    #[test]
+    fn test_get_many_mut() {
+        let mut map: HashMap<u32, u32> = (0..20).map(|i| (i, 100)).collect();
+        // Transfer budget between two entries in place.
+        let [a, b] = map.get_many_mut([&3, &17]).unwrap();
+        *a -= 30;
+        *b += 30;
+        assert_eq!(map[&3], 70);
+        assert_eq!(map[&17], 130);
+
+        assert!(map.get_many_mut([&1, &2, &1]).is_none());
+        assert!(map.get_many_mut([&1, &20]).is_none());
+        assert_eq!(map.get_many_mut::<u32, 0>([]), Some([]));
+
+        let [(k1, v1), (k2, v2)] = map.get_many_key_value_mut([&5, &6]).unwrap();
+        assert_eq!((*k1, *k2), (5, 6));
+        core::mem::swap(v1, v2);
+        *v1 += 1;
+        assert_eq!(map[&5], 101);
+
+        // Keys may live in either table during an incremental resize.
+        let mut map = HashMap::new();
+        let mut i = 0u32;
+        while !map.is_resizing() {
+            map.insert(i, i);
+            i += 1;
+        }
+        let [first, last] = map.get_many_mut([&0, &(i - 1)]).unwrap();
+        assert_ne!(first as *mut u32, last as *mut u32);
+        *first += 1000;
+        *last += 1000;
+        assert_eq!(map[&0], 1000);
+        assert_eq!(map[&(i - 1)], i - 1 + 1000);
+    }
+
+    #[test]
+    fn test_extract_if() {
+        let mut map: HashMap<u32, u32> = (0..100).map(|i| (i, i)).collect();
+        let mut odd: Vec<_> = map.extract_if(|_, v| *v % 2 == 1).collect();
+        odd.sort();
+        assert!(odd
+            .into_iter()
+            .eq((0..100).filter(|i| i % 2 == 1).map(|i| (i, i))));
+        assert_eq!(map.len(), 50);
+        assert!(map.iter().all(|(k, _)| k % 2 == 0));
+
+        // Dropping the iterator early keeps the entries it has not visited.
+        let extracted: Vec<_> = map
+            .extract_if(|_, v| {
+                *v += 1;
+                *v % 4 == 1
+            })
+            .take(5)
+            .collect();
+        assert_eq!(extracted.len(), 5);
+        assert_eq!(map.len(), 45);
+        for (k, _) in &extracted {
+            assert!(!map.contains_key(k));
+        }
+        for i in (0..100).filter(|i| i % 2 == 0) {
+            assert_eq!(
+                map.contains_key(&i),
+                !extracted.iter().any(|(k, _)| *k == i)
+            );
+        }
+
+        // Entries in both tables of an incremental resize are visited.
+        let mut map = HashMap::new();
+        let mut n = 0u32;
+        while !map.is_resizing() {
+            map.insert(n, n);
+            n += 1;
+        }
+        assert_eq!(map.extract_if(|_, _| true).count(), n as usize);
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn test_raw_api() {
+        let mut map: HashMap<String, usize> = HashMap::new();
+        let components = ["usr", "lib", "bin", "etc", "home", "root", "tmp"];
+        for (i, name) in components.iter().enumerate() {
+            let hash = map.hasher().hash_one(*name);
+            assert_eq!(map.raw_entry_insert(hash, name.to_string(), i), None);
+        }
+        let hash = map.hasher().hash_one("lib");
+        assert_eq!(map.raw_entry_insert(hash, "lib".to_string(), 100), Some(1));
+        assert_eq!(map.len(), components.len());
+
+        for name in components {
+            let hash = map.hasher().hash_one(name);
+            let raw = map.raw_get(hash, |k| k == name);
+            assert_eq!(raw.map(|(_, v)| v), map.get(name));
+            assert_eq!(raw.map(|(k, _)| k.as_str()), Some(name));
+        }
+        let hash = map.hasher().hash_one("var");
+        assert_eq!(map.raw_get(hash, |k| k == "var"), None);
+        // A matching key under the wrong hash is not found.
+        assert_eq!(map.raw_get(hash, |k| k == "usr"), None);
+
+        // An inconsistent hash gives a logical duplicate, not a corrupt map.
+        map.raw_entry_insert(hash, "usr".to_string(), 42);
+        assert_eq!(map.len(), components.len() + 1);
+        assert_eq!(map.get("usr"), Some(&0));
+        assert_eq!(
+            map.raw_get(hash, |k| k == "usr"),
+            Some((&"usr".to_string(), &42))
+        );
+    }
+
+    #[test]
+    fn test_exact_size() {
+        let mut map: HashMap<u32, u32> = (0..100).map(|i| (i, i)).collect();
+        map.retain(|k, _| k % 3 != 0);
+        let len = map.len();
+        assert_eq!(map.iter().len(), len);
+        assert_eq!(map.keys().len(), len);
+        assert_eq!(map.values().len(), len);
+        assert_eq!(map.iter_mut().len(), len);
+
+        let mut iter = map.iter();
+        for yielded in 1..=len {
+            iter.next().unwrap();
+            assert_eq!(iter.len(), len - yielded);
+            assert_eq!(iter.size_hint(), (len - yielded, Some(len - yielded)));
+        }
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.len(), 0);
+
+        // An exact size hint lets `collect` allocate once.
+        let before = allocations();
+        let keys: Vec<_> = map.keys().collect();
+        assert_eq!(allocations(), before + 1);
+        assert_eq!(keys.capacity(), len);
+        let before = allocations();
+        let pairs: Vec<_> = map.clone().into_iter().collect();
+        // One for the clone's table, one for the vector.
+        assert_eq!(allocations(), before + 2);
+        assert_eq!(pairs.len(), len);
+
+        let mut drain = map.drain();
+        assert_eq!(drain.len(), len);
+        drain.next();
+        assert_eq!(drain.len(), len - 1);
+    }
+
+    #[test]
+    fn test_named_iterators() {
+        /// A cursor resuming a walk over a map across calls.
+        struct Cursor<'a> {
+            iter: Iter<'a, u32, u32>,
+        }
+
+        impl Cursor<'_> {
+            fn next_batch(&mut self, n: usize) -> Vec<u32> {
+                self.iter.by_ref().take(n).map(|(k, _)| *k).collect()
+            }
+        }
+
+        let map: HashMap<u32, u32> = (0..10).map(|i| (i, i)).collect();
+        let mut cursor = Cursor { iter: map.iter() };
+        let mut seen = cursor.next_batch(4);
+        assert_eq!(seen.len(), 4);
+        let saved = cursor.iter.clone();
+        seen.extend(cursor.next_batch(100));
+        seen.sort();
+        assert!(seen.into_iter().eq(0..10));
+        assert_eq!(saved.len(), 6);
+        assert_eq!(cursor.next_batch(1), []);
+
+        let mut map = map;
+        let values: ValuesMut<'_, u32, u32> = map.values_mut();
+        assert_eq!(values.len(), 10);
+        for v in values {
+            *v *= 2;
+        }
+        assert_eq!(map[&3], 6);
+    }
+
+    #[test]
+    fn test_load_factor() {
+        for (pct, first_resize) in [(50, 9), (75, 13), (90, 15), (5, 2), (100, 16)] {
+            let mut map = HashMap::with_capacity_and_load_factor(0, pct);
+            assert!((10..=95).contains(&map.load_factor_percent()));
+            map.insert(0u32, 0);
+            assert_eq!(map.table.slots.len(), 16);
+            assert_eq!(map.capacity(), first_resize - 1);
+            let mut n = 1;
+            while map.table.slots.len() == 16 {
+                map.insert(n, n);
+                n += 1;
+            }
+            assert_eq!(n, first_resize as u32, "load factor {}%", pct);
+        }
+
+        let mut map = HashMap::with_capacity(12);
+        for i in 0..12u32 {
+            map.insert(i, i);
+        }
+        assert_eq!(map.table.slots.len(), 16);
+        // Raising the factor never resizes; lowering it below the current
+        // load does, right away.
+        map.set_load_factor_percent(95);
+        assert_eq!(map.table.slots.len(), 16);
+        map.set_load_factor_percent(50);
+        assert_eq!(map.table.slots.len(), 32);
+        assert!(!map.is_resizing());
+        assert!(map.len() < map.capacity());
+        for i in 0..12 {
+            assert_eq!(map.get(&i), Some(&i));
+        }
+        map.set_load_factor_percent(0);
+        assert_eq!(map.load_factor_percent(), 10);
+        // 128 slots hold 12 entries at 10%, but not the next insertion.
+        assert_eq!(map.table.slots.len(), 256);
+
+        // Entries not migrated yet count against the new factor too.
+        let mut map = HashMap::with_capacity(100);
+        let mut n = 0u32;
+        while !map.is_resizing() {
+            map.insert(n, n);
+            n += 1;
+        }
+        map.set_load_factor_percent(10);
+        assert!(!map.is_resizing());
+        assert!(map.len() < map.capacity());
+        for i in 0..n {
+            assert_eq!(map.get(&i), Some(&i));
+        }
+    }
+
+    #[test]
+    fn test_auto_shrink() {
+        let mut map = HashMap::new();
+        map.shrink_threshold(true);
+        for i in 0..10_000u32 {
+            map.insert(i, i);
+        }
+        let full = map.table.slots.len();
+        for i in 100..10_000 {
+            map.remove(&i);
+        }
+        assert_eq!(map.len(), 100);
+        assert!(map.table.slots.len() <= full / 16);
+        assert!(map.len() * 8 >= map.capacity());
+        for i in 0..100 {
+            assert_eq!(map.get(&i), Some(&i));
+        }
+
+        // Refilling grows back with one table per doubling, not by
+        // oscillating around the thresholds.
+        let before = allocations();
+        for i in 100..10_000 {
+            map.insert(i, i);
+        }
+        let grown = allocations() - before;
+        assert!(grown <= 6, "{} resizes", grown);
+        assert_eq!(map.table.slots.len(), full);
+
+        // Churn around one size does not resize at all.
+        map.retain(|k, _| *k < 1000);
+        let slots = map.table.slots.len();
+        let before = allocations();
+        for round in 0..10 {
+            for i in 1000..1200 {
+                map.insert(i, round);
+            }
+            for i in 1000..1200 {
+                map.remove(&i);
+            }
+        }
+        assert_eq!(allocations(), before);
+        assert_eq!(map.table.slots.len(), slots);
+
+        // Never below the requested capacity, and off by default.
+        let mut map = HashMap::with_capacity(1000);
+        let requested = map.table.slots.len();
+        map.shrink_threshold(true);
+        map.extend((0..5000u32).map(|i| (i, i)));
+        map.drain();
+        assert_eq!(map.table.slots.len(), requested);
+        let mut map: HashMap<u32, u32> = (0..5000).map(|i| (i, i)).collect();
+        let full = map.table.slots.len();
+        map.retain(|_, _| false);
+        assert_eq!(map.table.slots.len(), full);
+    }
+
+    #[test]
+    fn test_bucket_stats() {
+        let mut map = HashMap::with_hasher(SimpleBuildHasher::with_seed(0));
+        assert_eq!(map.bucket_stats(), BucketStats::default());
+
+        for i in 0..10_000u64 {
+            map.insert(i, i);
+        }
+        map.finish_resize();
+        let stats = map.bucket_stats();
+        assert_eq!(stats.buckets, map.table.slots.len());
+        assert_eq!(stats.occupied, 10_000);
+        assert_eq!(stats.empty, stats.buckets - stats.occupied);
+        // Sequential keys are the classic failure of a weak mix: they would
+        // land in a handful of clustered runs instead of spreading out.
+        assert!(stats.max_chain < 64, "{stats:?}");
+        assert!(stats.avg_chain_x100 < 400, "{stats:?}");
+        #[cfg(debug_assertions)]
+        map.dump_distribution();
+
+        for i in 0..5_000u64 {
+            map.remove(&i);
+        }
+        let stats = map.bucket_stats();
+        assert_eq!(stats.occupied, 5_000);
+        assert_eq!(stats.empty, stats.buckets - 10_000);
+    }
+
+    #[test]
+    fn test_resalt() {
+        let mut map = HashMap::with_hasher(SimpleBuildHasher::with_seed(0));
+        for i in 0..1000u32 {
+            map.insert(i, i);
+        }
+        map.finish_resize();
+        let salt = map.table.salt;
+        let layout: Vec<_> = map.keys().copied().collect();
+
+        // Force a resize: every key is placed again under a new salt.
+        map.reserve(map.capacity());
+        assert_ne!(map.table.salt, salt);
+        for i in 0..1000 {
+            assert_eq!(map.get(&i), Some(&i));
+        }
+
+        let slots = map.table.slots.len();
+        let salt = map.table.salt;
+        map.remove(&0);
+        map.rehash_with_new_salt();
+        assert_ne!(map.table.salt, salt);
+        assert_eq!(map.table.slots.len(), slots);
+        assert_eq!(map.table.deleted, 0);
+        assert_eq!(map.get(&0), None);
+        for i in 1..1000 {
+            assert_eq!(map.get(&i), Some(&i));
+        }
+        assert!(map.keys().ne(layout.iter()));
+
+        // Shrinking rebuilds the table under a new salt too.
+        map.shrink_threshold(true);
+        let salt = map.table.salt;
+        map.retain(|&k, _| k < 50);
+        assert_ne!(map.table.salt, salt);
+        for i in 1..50 {
+            assert_eq!(map.get(&i), Some(&i));
+        }
+        map.extend((0..1000).map(|i| (i, i)));
+        map.finish_resize();
+        let (slots, salt) = (map.table.slots.len(), map.table.salt);
+        map.drain();
+        assert!(map.table.slots.len() < slots);
+        assert_ne!(map.table.salt, salt);
+
+        // Keys migrated incrementally into a table with another salt.
+        let mut map = HashMap::with_hasher(SimpleBuildHasher::with_seed(0));
+        for i in 0..1000u32 {
+            map.insert(i, i);
+            for j in 0..=i {
+                assert_eq!(map.get(&j), Some(&j));
+            }
+        }
+    }
+
+    #[test]
+    fn test_rehash_repairs_hashes() {
+        let mut map = HashMap::with_seed(7);
+        for i in 0..100u32 {
+            map.insert(i, i);
+        }
+        let hash = map.hasher().hash_one(1000u32);
+        map.raw_entry_insert(!hash, 1000, 1000);
+        assert_eq!(map.raw_get(!hash, |&k| k == 1000), Some((&1000, &1000)));
+
+        map.rehash_with_new_salt();
+        assert_eq!(map.get(&1000), Some(&1000));
+        assert_eq!(map.raw_get(hash, |&k| k == 1000), Some((&1000, &1000)));
+        for i in 0..100 {
+            assert_eq!(map.get(&i), Some(&i));
+        }
+    }
+
+    #[test]
+    fn test_random_salts() {
+        // Seeded maps derive their salts from the seed, to stay reproducible.
+        let seeded = |map: &HashMap<u32, u32>| map.hash(&map.salts) == map.table.salt;
+        let mut map = HashMap::with_seed(7);
+        map.insert(0, 0);
+        assert!(seeded(&map));
+        let mut map = HashMap::builder().seed(7).build();
+        map.insert(0, 0);
+        assert!(seeded(&map));
+
+        let mut map = HashMap::new();
+        map.insert(0, 0);
+        map.rehash_with_new_salt();
+        assert_eq!(seeded(&map), cfg!(feature = "deterministic-hash"));
+        let mut map = HashMap::builder().build();
+        map.insert(0, 0);
+        assert_eq!(seeded(&map), cfg!(feature = "deterministic-hash"));
+    }
+
+    /// Hashes the first `len` bytes of `00 01 02 ...` under the key
+    /// `00 01 .. 0f`, the setup of the SipHash test vectors.
+    fn sip_vector<H: Hasher>(mut hasher: H, len: u8) -> u64 {
+        hasher.write(&(0..len).collect::<Vec<_>>());
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_sip_vectors() {
+        let k0 = 0x0706_0504_0302_0100;
+        let k1 = 0x0f0e_0d0c_0b0a_0908;
+        // SipHash-2-4, from the paper (appendix A for 15 bytes) and its
+        // reference implementation.
+        let sip24 = || Sip::<2, 4>::new(k0, k1);
+        assert_eq!(sip_vector(sip24(), 0), 0x726f_db47_dd0e_0e31);
+        assert_eq!(sip_vector(sip24(), 1), 0x74f8_39c5_93dc_67fd);
+        assert_eq!(sip_vector(sip24(), 7), 0xab02_00f5_8b01_d137);
+        assert_eq!(sip_vector(sip24(), 8), 0x93f5_f579_9a93_2462);
+        assert_eq!(sip_vector(sip24(), 15), 0xa129_ca61_49be_45e5);
+        assert_eq!(sip_vector(sip24(), 63), 0x958a_324c_eb06_4572);
+        // SipHash-1-3, from the reference implementation built with 1
+        // compression and 3 finalization rounds.
+        let sip13 = || SipHasher13::new_with_keys(k0, k1);
+        assert_eq!(sip_vector(sip13(), 0), 0xabac_0158_050f_c4dc);
+        assert_eq!(sip_vector(sip13(), 1), 0xc9f4_9bf3_7d57_ca93);
+        assert_eq!(sip_vector(sip13(), 7), 0xd392_7d98_9bb1_1140);
+        assert_eq!(sip_vector(sip13(), 8), 0x3690_9511_8d29_9a8e);
+        assert_eq!(sip_vector(sip13(), 15), 0xd320_d86d_2a51_9956);
+        assert_eq!(sip_vector(sip13(), 63), 0x9d19_9062_b7bb_b3a8);
+
+        // Splitting the input across writes does not change the hash.
+        let mut split = sip13();
+        let bytes: Vec<u8> = (0..63).collect();
+        for chunk in [&bytes[..3], &bytes[3..4], &bytes[4..20], &bytes[20..]] {
+            split.write(chunk);
+        }
+        assert_eq!(split.finish(), 0x9d19_9062_b7bb_b3a8);
+
+        let a = SipBuildHasher::with_seed(1);
+        assert_eq!(
+            a.hash_one("key"),
+            SipBuildHasher::with_seed(1).hash_one("key")
+        );
+        assert_ne!(
+            a.hash_one("key"),
+            SipBuildHasher::with_seed(2).hash_one("key")
+        );
+    }
+
+    #[test]
+    fn test_sip_collision_resistance() {
+        const KEYS: usize = 200;
+        // An attacker who knows the seed picks keys whose hashes share their
+        // low 10 bits, so that they all start probing from the same slot of
+        // a table of up to 1024 slots.
+        let simple = SimpleBuildHasher::with_seed(0);
+        let target = simple.hash_one(0u64) & 0x3ff;
+        let keys: Vec<u64> = (0..)
+            .filter(|k| simple.hash_one(k) & 0x3ff == target)
+            .take(KEYS)
+            .collect();
+
+        let mut map = HashMap::with_capacity_and_hasher(KEYS, simple);
+        let mut sip = HashMap::with_capacity_and_hasher(KEYS, SipBuildHasher::with_seed(0));
+        for &k in &keys {
+            map.insert(k, ());
+            sip.insert(k, ());
+        }
+        assert!(!map.is_resizing() && !sip.is_resizing());
+        assert_eq!(map.bucket_stats().max_chain, KEYS);
+        let stats = sip.bucket_stats();
+        assert!(stats.max_chain < 16, "{stats:?}");
+        for k in &keys {
+            assert!(sip.contains_key(k));
+        }
+    }
+
+    #[test]
+    fn test_integer_avalanche() {
+        let hasher = SimpleBuildHasher::with_seed(0);
+        let mut state = 0x853c_49e6_748f_ea9b;
+        let (mut flipped, mut trials) = (0, 0);
+        for _ in 0..256 {
+            let x = xorshift(&mut state);
+            let h = hasher.hash_one(x);
+            for bit in 0..64 {
+                flipped += (h ^ hasher.hash_one(x ^ 1 << bit)).count_ones();
+                trials += 64;
+            }
+        }
+        // An ideal hash flips half of the output bits, 32 on average.
+        assert!(
+            flipped >= trials * 30 / 64,
+            "{flipped} of {trials} bits flipped"
+        );
+
+        // Likewise for short byte strings, hashed by `write`.
+        let (mut flipped, mut trials) = (0, 0);
+        for _ in 0..256 {
+            let x = xorshift(&mut state).to_le_bytes();
+            let h = hasher.hash_one(x);
+            for bit in 0..64 {
+                let y = (u64::from_le_bytes(x) ^ 1 << bit).to_le_bytes();
+                flipped += (h ^ hasher.hash_one(y)).count_ones();
+                trials += 64;
+            }
+        }
+        assert!(
+            flipped >= trials * 30 / 64,
+            "{flipped} of {trials} bits flipped"
+        );
+
+        // Small integers must not collide either, including zero.
+        let hashes: alloc::collections::BTreeSet<_> =
+            (0..10_000u32).map(|i| hasher.hash_one(i)).collect();
+        assert_eq!(hashes.len(), 10_000);
+        assert_ne!(hasher.hash_one((0u8, 0u8)), hasher.hash_one(0u8));
+    }
+
+    #[test]
+    fn test_with_seed() {
+        let build = |mut map: HashMap<u32, u32>| {
+            for i in 0..1000u32 {
+                map.insert(i.wrapping_mul(0x9e37_79b9), i);
+            }
+            for i in (0..1000u32).step_by(3) {
+                map.remove(&i.wrapping_mul(0x9e37_79b9));
+            }
+            map
+        };
+        let a = build(HashMap::with_seed(7));
+        let b = build(HashMap::with_seed(7));
+        assert!(a.iter().eq(b.iter()));
+        let c = build(HashMap::with_capacity_and_seed(2000, 7));
+        assert!(c.iter().eq(c.clone().iter()));
+        assert_eq!(c.len(), a.len());
+        if cfg!(feature = "deterministic-hash") {
+            assert!(build(HashMap::new())
+                .iter()
+                .eq(build(HashMap::new()).iter()));
+        }
+    }
+
+    #[test]
+    fn test_key_recovery() {
+        use alloc::string::{String, ToString};
+
+        let mut interned: HashMap<String, usize> = HashMap::new();
+        for word in ["alpha", "beta", "gamma"] {
+            let len = interned.len();
+            interned.entry(word.to_string()).or_insert(len);
+        }
+        let canonical: Vec<*const u8> = ["alpha", "beta", "gamma"]
+            .iter()
+            .map(|w| interned.get_key_value(*w).unwrap().0.as_ptr())
+            .collect();
+        // Repeated probes, also through entries, return the same stored keys.
+        for _ in 0..3 {
+            for (word, &ptr) in ["alpha", "beta", "gamma"].iter().zip(&canonical) {
+                let (key, _) = interned.get_key_value(*word).unwrap();
+                assert_eq!(key.as_ptr(), ptr);
+                match interned.entry(word.to_string()) {
+                    Entry::Occupied(entry) => assert_eq!(entry.key().as_ptr(), ptr),
+                    Entry::Vacant(_) => panic!("{} is not interned", word),
+                }
+            }
+        }
+        assert_eq!(interned.get_key_value("delta"), None);
+
+        // A key that was not inserted is handed back, allocation included.
+        let probe = String::from("delta");
+        let ptr = probe.as_ptr();
+        let entry = interned.entry(probe);
+        assert_eq!(entry.key(), "delta");
+        match entry {
+            Entry::Vacant(entry) => assert_eq!(entry.into_key().as_ptr(), ptr),
+            Entry::Occupied(_) => panic!("delta is interned"),
+        }
+        assert_eq!(interned.len(), 3);
+
+        match interned.entry(String::from("beta")) {
+            Entry::Occupied(entry) => {
+                let (key, value) = entry.remove_entry();
+                assert_eq!((key.as_ptr(), value), (canonical[1], 1));
+            }
+            Entry::Vacant(_) => panic!("beta is not interned"),
+        }
+        let (key, value) = interned.remove_entry("gamma").unwrap();
+        assert_eq!((key.as_ptr(), value), (canonical[2], 2));
+        assert_eq!(interned.len(), 1);
+    }
+
+    #[test]
+    fn test_append() {
+        let mut global = HashMap::with_hasher(SimpleBuildHasher::with_seed(0));
+        let mut cpu = HashMap::with_hasher(SimpleBuildHasher::with_seed(0));
+        for i in 0..100u32 {
+            global.insert(i, 0);
+        }
+        for i in 50..400u32 {
+            cpu.insert(i, 1);
+        }
+        cpu.finish_resize();
+        let cpu_slots = cpu.table.slots.len();
+
+        global.append(&mut cpu);
+        assert_eq!(global.len(), 400);
+        for i in 0..400 {
+            assert_eq!(global.get(&i), Some(&u32::from(i >= 50)));
+        }
+        // Reserved once, for both maps: no resize was started afterwards.
+        assert!(!global.is_resizing());
+        assert!(cpu.is_empty());
+        assert_eq!(cpu.table.slots.len(), cpu_slots);
+
+        // The source can be refilled and merged again.
+        cpu.insert(7, 2);
+        global.append(&mut cpu);
+        assert_eq!(global[&7], 2);
+        assert_eq!(global.len(), 400);
+        global.append(&mut cpu);
+        assert_eq!(global.len(), 400);
+    }
+
+    #[test]
+    fn test_from_array() {
+        let before = allocations();
+        let errno = HashMap::from([
+            (1, "EPERM"),
+            (2, "ENOENT"),
+            (3, "ESRCH"),
+            (4, "EINTR"),
+            (5, "EIO"),
+            (6, "ENXIO"),
+            (7, "E2BIG"),
+            (8, "ENOEXEC"),
+            (9, "EBADF"),
+            (10, "ECHILD"),
+            (11, "EAGAIN"),
+            (12, "ENOMEM"),
+        ]);
+        // A single table, allocated up front.
+        assert_eq!(allocations() - before, 1);
+        assert!(!errno.is_resizing());
+        assert_eq!(errno.len(), 12);
+        for (code, name) in [(1, "EPERM"), (5, "EIO"), (9, "EBADF"), (12, "ENOMEM")] {
+            assert_eq!(errno[&code], name);
+        }
+        assert_eq!(errno.get(&13), None);
+
+        let pairs = [
+            ("txt", "text/plain"),
+            ("png", "image/png"),
+            ("txt", "text/html"),
+        ];
+        let before = allocations();
+        let mime = HashMap::from_pairs(&pairs);
+        assert_eq!(allocations() - before, 1);
+        assert_eq!(mime.len(), 2);
+        assert_eq!(mime["txt"], "text/html");
+        assert_eq!(HashMap::from(pairs), mime);
+        assert!(HashMap::<u32, u32>::from([]).is_empty());
+    }
+
+    fn assert_send<T: Send>() {}
+    fn assert_sync<T: Sync>() {}
+
+    /// Compiles only if `T` is not `Send`: otherwise both impls apply and
+    /// the trait parameter cannot be inferred.
+    trait NotSend<A> {
+        fn check() {}
+    }
+    impl<T: ?Sized> NotSend<()> for T {}
+    impl<T: ?Sized + Send> NotSend<u8> for T {}
+
+    #[test]
+    fn test_send_sync() {
+        use alloc::rc::Rc;
+        use alloc::string::String;
+
+        type Map = HashMap<String, Vec<u8>>;
+        assert_send::<Map>();
+        assert_sync::<Map>();
+        assert_send::<HashMap<String, Vec<u8>, SipBuildHasher>>();
+        assert_sync::<HashMap<String, Vec<u8>, SipBuildHasher>>();
+        assert_send::<Iter<'_, String, Vec<u8>>>();
+        assert_sync::<Iter<'_, String, Vec<u8>>>();
+        assert_send::<IterMut<'_, String, Vec<u8>>>();
+        assert_send::<IntoIter<String, Vec<u8>>>();
+        assert_send::<Drain<'_, String, Vec<u8>>>();
+        assert_send::<ExtractIf<'_, String, Vec<u8>, fn(&String, &mut Vec<u8>) -> bool>>();
+        assert_send::<Keys<'_, String, Vec<u8>>>();
+        assert_send::<Values<'_, String, Vec<u8>>>();
+        assert_send::<ValuesMut<'_, String, Vec<u8>>>();
+        assert_send::<Entry<'_, String, Vec<u8>>>();
+        assert_sync::<Entry<'_, String, Vec<u8>>>();
+        assert_send::<crate::hashset::HashSet<String>>();
+        assert_sync::<crate::hashset::HashSet<String>>();
+
+        <HashMap<Rc<()>, ()> as NotSend<_>>::check();
+        <HashMap<(), Rc<()>> as NotSend<_>>::check();
+        <IntoIter<Rc<()>, ()> as NotSend<_>>::check();
+    }
+
+    ::std::thread_local! {
+        /// Number of `Hash` calls left before `PanickyKey` panics, if armed.
+        static HASHES_LEFT: Cell<Option<usize>> = const { Cell::new(None) };
+    }
+
+    /// A key whose `Hash` panics once `HASHES_LEFT` runs out.
+    #[derive(PartialEq, Eq, Debug)]
+    struct PanickyKey(u32);
+
+    impl Hash for PanickyKey {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            if let Some(left) = HASHES_LEFT.get() {
+                assert!(left > 1, "hash of {:?} panics", self);
+                HASHES_LEFT.set(Some(left - 1));
+            }
+            self.0.hash(state);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(feature = "debug-validate", ignore = "validation hashes every key")]
+    fn test_panicking_hash_keeps_entries() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        // Large enough that the migration takes more than 4 insertions.
+        let mut map = HashMap::with_capacity(100);
+        let mut n = 0;
+        while map.len() < map.capacity() {
+            map.insert(PanickyKey(n), n);
+            n += 1;
+        }
+        assert!(!map.is_resizing());
+
+        // The first insertion starts an incremental resize; the 5th hash
+        // panics with the migration under way.
+        HASHES_LEFT.set(Some(5));
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            for i in n..n + 10 {
+                map.insert(PanickyKey(i), i);
+            }
+        }));
+        assert!(result.is_err());
+        assert!(map.is_resizing());
+        assert_eq!(map.len(), n as usize + 4);
+
+        // Migrating, resizing and shrinking never hash a key.
+        HASHES_LEFT.set(Some(1));
+        map.finish_resize();
+        map.reserve(100);
+        map.shrink_to_fit();
+        HASHES_LEFT.set(None);
+
+        // Rehashing does, but only moves entries once every key is hashed.
+        HASHES_LEFT.set(Some(5));
+        let result = catch_unwind(AssertUnwindSafe(|| map.rehash_with_new_salt()));
+        assert!(result.is_err());
+        HASHES_LEFT.set(None);
+
+        assert_eq!(map.len(), n as usize + 4);
+        for i in 0..n + 4 {
+            assert_eq!(map.get(&PanickyKey(i)), Some(&i));
+        }
+        assert_eq!(map.get(&PanickyKey(n + 4)), None);
+    }
+
+    #[test]
+    fn test_resize_allocations() {
+        // Every resize costs one allocation for the new table and one
+        // deallocation for the old one, whatever the number of slots.
+        let mut map = HashMap::new();
+        let mut n = 0u32;
+        for _ in 0..12 {
+            while map.len() < map.capacity() {
+                map.insert(n, n);
+                n += 1;
+            }
+            let slots = map.table.slots.len();
+            let (allocs, deallocs) = (allocations(), deallocations());
+            map.insert(n, n);
+            n += 1;
+            while map.is_resizing() {
+                map.insert(n, n);
+                n += 1;
+            }
+            assert_eq!(map.table.slots.len(), slots.max(8) * 2);
+            assert_eq!(allocations() - allocs, 1, "growing {} slots", slots);
+            assert_eq!(
+                deallocations() - deallocs,
+                (slots > 0) as usize,
+                "growing {} slots",
+                slots
+            );
+        }
+        assert!(map.table.slots.len() >= 1 << 14);
+        for i in 0..n {
+            assert_eq!(map[&i], i);
+        }
+    }
+
+    #[test]
+    fn test_steady_state_allocations() {
+        for n in [10u32, 1000, 10_000] {
+            let (allocs, deallocs) = (allocations(), deallocations());
+            let mut map: HashMap<u32, u32> = (0..n).map(|i| (i, i)).collect();
+            map.finish_resize();
+            // One live table, whatever the number of entries.
+            assert_eq!(allocations() - allocs, deallocations() - deallocs + 1);
+
+            // Replacing entries reuses their slots and tombstones.
+            let before = allocations();
+            for i in 0..n {
+                map.remove(&i);
+                map.insert(i + n, i);
+            }
+            let churned = allocations() - before;
+            assert!(churned <= 1, "{} entries: {} allocations", n, churned);
+        }
+    }
+
+    #[test]
+    fn test_get_or_insert_with() {
+        let mut map = HashMap::new();
+        let mut calls = 0;
+        *map.get_or_insert_with(1, || {
+            calls += 1;
+            10
+        }) += 1;
+        assert_eq!(map[&1], 11);
+        assert_eq!(*map.get_or_insert_with(1, || unreachable!()), 11);
+        assert_eq!(*map.get_or_insert(1, 0), 11);
+        assert_eq!(*map.get_or_insert(2, 20), 20);
+
+        // Across the resize boundary, every key is hashed exactly once and
+        // the closure runs only for the missing ones.
+        let mut map = HashMap::new();
+        for i in 0..200 {
+            HASHES_LEFT.set(Some(2));
+            let full = map.len() == map.capacity();
+            let capacity = map.capacity();
+            let value = map.get_or_insert_with(PanickyKey(i), || {
+                calls += 1;
+                i
+            });
+            assert_eq!(*value, i);
+            assert!(!full || map.capacity() > capacity);
+            HASHES_LEFT.set(Some(2));
+            assert_eq!(*map.get_or_insert_with(PanickyKey(i), || unreachable!()), i);
+        }
+        HASHES_LEFT.set(None);
+        assert_eq!(calls, 201);
+        assert_eq!(map.len(), 200);
+    }
+
+    #[test]
+    fn test_double_ended_iter() {
+        let mut map = HashMap::with_capacity(50);
+        let full = map.capacity() as u32;
+        // Mid-resize, the entries of both tables are visited from both ends.
+        for i in 0..full + 2 {
+            map.insert(i, i * 10);
+        }
+        assert!(map.is_resizing());
+        let n = map.len();
+
+        let forward: Vec<_> = map.iter().collect();
+        let mut backward: Vec<_> = map.iter().rev().collect();
+        backward.reverse();
+        assert_eq!(forward, backward);
+        assert_eq!(forward.len(), n);
+
+        // Clones continue independently from where the original stopped.
+        let mut iter = map.keys();
+        let front: Vec<_> = iter.by_ref().take(10).collect();
+        let copy = iter.clone();
+        let rest: Vec<_> = iter.collect();
+        assert_eq!(copy.rev().count(), n - 10);
+        let mut all: Vec<u32> = front.into_iter().chain(rest).copied().collect();
+        all.sort();
+        assert_eq!(all, (0..n as u32).collect::<Vec<_>>());
+
+        // Meeting in the middle yields every entry once.
+        let mut values = map.values();
+        let mut seen = Vec::new();
+        while let Some(v) = values.next() {
+            seen.push(*v);
+            if let Some(v) = values.next_back() {
+                seen.push(*v);
+            }
+            assert_eq!(values.len(), n - seen.len());
+        }
+        assert_eq!(values.next_back(), None);
+        seen.sort();
+        assert_eq!(seen, (0..n as u32).map(|i| i * 10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_shared_hasher() {
+        assert_eq!(
+            SimpleBuildHasher::with_seed(u128::MAX - 1).seed(),
+            u128::MAX - 1
+        );
+        assert_eq!(
+            SipBuildHasher::with_seed(u128::MAX - 1).seed(),
+            u128::MAX - 1
+        );
+        let map: HashMap<u32, u32> = HashMap::with_seed(7);
+        assert_eq!(map.hasher().seed(), 7);
+
+        // A hot map and a cold one, sharing the hash state.
+        let mut cold: HashMap<u32, u64> = (0..100).map(|i| (i, i as u64)).collect();
+        let mut hot: HashMap<u32, u64> = HashMap::with_hasher_from(&cold);
+        assert_eq!(hot.hasher().seed(), cold.hasher().seed());
+        for key in [3, 14, 15, 92] {
+            let hash = hot.hasher().hash_one(key);
+            assert_eq!(hash, cold.hasher().hash_one(key));
+            assert_eq!(hot.raw_get(hash, |k| *k == key), None);
+            let (_, value) = cold.remove_entry(&key).unwrap();
+            assert_eq!(hot.raw_entry_insert(hash, key, value), None);
+            assert_eq!(
+                hot.raw_get(hash, |k| *k == key),
+                Some((&key, &(key as u64)))
+            );
+            assert_eq!(cold.raw_get(hash, |k| *k == key), None);
+        }
+        assert_eq!((hot.len(), cold.len()), (4, 96));
+        for key in 0..100 {
+            let hash = cold.hasher().hash_one(key);
+            let found = hot
+                .raw_get(hash, |k| *k == key)
+                .or(cold.raw_get(hash, |k| *k == key));
+            assert_eq!(found, Some((&key, &(key as u64))));
+            assert_eq!(hot.contains_key(&key), [3, 14, 15, 92].contains(&key));
+        }
+    }
+
+    #[test]
+    fn test_std_names() {
+        // Written against `std::collections::hash_map`.
+        fn word_counts(text: &str) -> HashMap<&str, usize, RandomState> {
+            let mut counts = HashMap::with_hasher(RandomState::new());
+            for word in text.split_whitespace() {
+                match counts.entry(word) {
+                    Entry::Occupied(mut e) => *e.get_mut() += 1,
+                    Entry::Vacant(e) => {
+                        e.insert(1);
+                    }
+                }
+            }
+            counts
+        }
+        let counts = word_counts("a b a c b a");
+        assert_eq!((counts["a"], counts["b"], counts["c"]), (3, 2, 1));
+
+        let hash = |x: u32| {
+            let mut hasher = DefaultHasher::new();
+            x.hash(&mut hasher);
+            hasher.finish()
+        };
+        assert_eq!(hash(42), hash(42));
+        assert_ne!(hash(42), hash(43));
+        assert_eq!(
+            SimpleHasher::new().finish(),
+            SimpleHasher::default().finish()
+        );
+        assert_eq!(SipHasher13::new().finish(), SipHasher13::default().finish());
+    }
+
+    #[test]
+    fn test_builder() {
+        let map: HashMap<u32, u32> = HashMap::builder().build();
+        assert_eq!(map.load_factor_percent(), DEFAULT_LOAD_FACTOR);
+        assert_eq!(map.capacity(), 0);
+
+        let build = || {
+            HashMap::builder()
+                .capacity(100)
+                .seed(42)
+                .load_factor(80)
+                .shrink_threshold(true)
+                .build()
+        };
+        let mut map = build();
+        assert_eq!(map.load_factor_percent(), 80);
+        assert_eq!(map.hasher().seed(), 42);
+        let capacity = map.capacity();
+        assert!(capacity >= 100);
+        assert_eq!(capacity, map.table.slots.len() * 80 / 100);
+        let before = allocations();
+        for i in 0..capacity as u32 {
+            map.insert(i, i);
+        }
+        assert_eq!(allocations(), before);
+        map.insert(capacity as u32, 0);
+        assert!(map.capacity() > capacity);
+
+        // Equal seeds, equal layouts.
+        let mut other = build();
+        other.extend((0..=capacity as u32).map(|i| (i, i)));
+        assert!(map.keys().eq(other.keys()));
+
+        // The capacity asked for is kept when shrinking.
+        map.retain(|k, _| *k < 10);
+        assert_eq!(map.capacity(), capacity);
+        other.retain(|_, _| false);
+        assert_eq!(other.capacity(), capacity);
+
+        let map: HashMap<&str, u32, _> = HashMap::builder()
+            .hasher(SipBuildHasher::with_seed(1))
+            .capacity(10)
+            .build();
+        assert!(map.capacity() >= 10);
+        assert_eq!(map.hasher().seed(), 1);
+    }
+
+    #[test]
+    fn test_check_invariants() {
+        use InvariantViolation::*;
+
+        let mut map: HashMap<u32, u32> = HashMap::with_seed(3);
+        map.extend((0..40).map(|i| (i, i)));
+        map.finish_resize();
+        for i in 0..10 {
+            map.remove(&i);
+        }
+        assert_eq!(map.check_invariants(), Ok(()));
+        let slot_of = |map: &HashMap<u32, u32>, key: u32| {
+            let hash = map.hash(&key);
+            map.table.find(hash, &key).unwrap()
+        };
+
+        let mut bad = map.clone();
+        bad.table.size += 1;
+        let size = map.table.size;
+        assert_eq!(
+            bad.check_invariants(),
+            Err(SizeMismatch {
+                recorded: size + 1,
+                actual: size
+            })
+        );
+
+        let mut bad = map.clone();
+        bad.table.deleted -= 1;
+        let deleted = map.table.deleted;
+        assert_eq!(
+            bad.check_invariants(),
+            Err(DeletedMismatch {
+                recorded: deleted - 1,
+                actual: deleted
+            })
+        );
+
+        let mut bad = map.clone();
+        let index = slot_of(&bad, 20);
+        if let Slot::Full(b) = &mut bad.table.slots[index] {
+            b.hash ^= 1;
+        }
+        assert_eq!(bad.check_invariants(), Err(StaleHash { index }));
+
+        // Move an entry one slot on, leaving a hole in its probe sequence.
+        let mut bad = map.clone();
+        let mask = bad.table.mask;
+        let index = (10..40)
+            .map(|key| slot_of(&bad, key))
+            .find(|&idx| matches!(bad.table.slots[(idx + 1) & mask], Slot::Empty))
+            .unwrap();
+        bad.table.slots.swap(index, (index + 1) & mask);
+        let index = (index + 1) & mask;
+        assert_eq!(bad.check_invariants(), Err(Unreachable { index }));
+
+        // A second copy of a key, further along its probe sequence.
+        let mut bad = map.clone();
+        let hash = bad.hash(&20);
+        bad.table.insert_unique(Bucket {
+            hash,
+            key: 20,
+            value: 0,
+        });
+        let first = slot_of(&bad, 20);
+        let index = (0..bad.table.slots.len())
+            .find(|&idx| {
+                idx != first && matches!(&bad.table.slots[idx], Slot::Full(b) if b.key == 20)
+            })
+            .unwrap();
+        assert_eq!(bad.check_invariants(), Err(DuplicateKey { index }));
+
+        // Mid-resize: the cursor skipped an entry, or an entry is in both
+        // tables.
+        let mut map = HashMap::with_capacity_and_seed(100, 3);
+        map.extend((0..=map.capacity() as u32).map(|i| (i, i)));
+        assert!(map.is_resizing());
+        assert_eq!(map.check_invariants(), Ok(()));
+
+        let mut bad = map.clone();
+        let index = (bad.migrated..bad.old.slots.len())
+            .find(|&idx| matches!(bad.old.slots[idx], Slot::Full(..)))
+            .unwrap();
+        bad.migrated = index + 1;
+        assert_eq!(bad.check_invariants(), Err(Unmigrated { index }));
+
+        let mut bad = map.clone();
+        let Slot::Full(b) = bad.old.slots[index].clone() else {
+            unreachable!()
+        };
+        bad.table.insert_unique(b);
+        let offset = bad.old.slots.len();
+        let index = (offset..offset + bad.table.slots.len())
+            .find(
+                |&idx| match (&bad.table.slots[idx - offset], &map.old.slots[index]) {
+                    (Slot::Full(a), Slot::Full(b)) => a.key == b.key,
+                    _ => false,
+                },
+            )
+            .unwrap();
+        assert_eq!(bad.check_invariants(), Err(DuplicateKey { index }));
+    }
+
+    #[test]
+    fn test_sorted_snapshot() {
+        use alloc::format;
+        use alloc::string::String;
+
+        let config = |seed| {
+            let mut map: HashMap<String, String> = HashMap::with_seed(seed);
+            for i in (0..500).rev() {
+                map.insert(format!("key{:03}", i), format!("value{}", i * 7));
+            }
+            // Leave some tombstones behind.
+            for i in 500..520 {
+                map.insert(format!("key{:03}", i), String::new());
+            }
+            for i in 500..520 {
+                map.remove(&format!("key{:03}", i));
+            }
+            map
+        };
+        let map = config(1);
+        let pairs = map.to_sorted_vec();
+        assert_eq!(pairs.len(), 500);
+        assert_eq!(pairs[0], ("key000".into(), "value0".into()));
+        assert_eq!(pairs[499], ("key499".into(), "value3493".into()));
+        assert!(pairs.windows(2).all(|w| w[0].0 < w[1].0));
+
+        // The export does not depend on the seed or the history.
+        let other = config(2);
+        assert!(!map.keys().eq(other.keys()));
+        assert_eq!(other.to_sorted_vec(), pairs);
+
+        let before = allocations();
+        let loaded = HashMap::from_sorted_pairs(pairs);
+        assert_eq!(allocations() - before, 1);
+        assert_eq!(loaded, map);
+        assert_eq!(loaded.to_sorted_vec(), map.to_sorted_vec());
+
+        let unsorted =
+            ::std::panic::catch_unwind(|| HashMap::from_sorted_pairs(alloc::vec![(2, 0), (1, 0)]));
+        assert!(unsorted.is_err());
+        let duplicated =
+            ::std::panic::catch_unwind(|| HashMap::from_sorted_pairs(alloc::vec![(1, 0), (1, 1)]));
+        assert!(duplicated.is_err());
+    }
+
+    /// Returns the chi-square statistic of the hashes of `keys` spread over
+    /// 64 buckets by their low bits, as a table of 64 slots does.
+    fn chi_square<T: Hash>(keys: impl Iterator<Item = T>) -> f64 {
+        let hasher = SimpleBuildHasher::with_seed(0);
+        let mut counts = [0u32; 64];
+        let mut n = 0;
+        for key in keys {
+            counts[hasher.hash_one(key) as usize & 63] += 1;
+            n += 1;
+        }
+        let expected = n as f64 / 64.0;
+        counts
+            .iter()
+            .map(|&c| (c as f64 - expected).powi(2) / expected)
+            .sum()
+    }
+
+    #[test]
+    fn test_hash_distribution() {
+        // With 63 degrees of freedom, the statistic averages 63 with a
+        // standard deviation of 11; 110 is beyond 4 of them.
+        for (name, chi2) in [
+            ("u32", chi_square(0..64_000u32)),
+            ("strided u32", chi_square((0..64_000u32).map(|i| i << 6))),
+            ("4 bytes", chi_square((0..64_000u32).map(u32::to_be_bytes))),
+            (
+                "strided 4 bytes",
+                chi_square((0..64_000u32).map(|i| (i << 8).to_be_bytes())),
+            ),
+        ] {
+            assert!(chi2 < 110.0, "{}: chi-square {}", name, chi2);
+        }
+    }
+
+    #[test]
+    fn test_finish128() {
+        let digest = |bytes: &[u8]| {
+            let mut hasher = SimpleHasher::new();
+            hasher.write(bytes);
+            (hasher.finish128(), hasher.finish())
+        };
+        let (d, h) = digest(b"content");
+        assert_eq!(d as u64, h);
+        assert_eq!(digest(b"content").0, d);
+
+        // Flipping any input bit flips about half of the 128 output bits.
+        let input = *b"arceos\0\0";
+        let mut flipped = 0;
+        for bit in 0..64 {
+            let other = (u64::from_le_bytes(input) ^ 1 << bit).to_le_bytes();
+            flipped += (digest(&input).0 ^ digest(&other).0).count_ones();
+        }
+        assert!(flipped >= 64 * 60, "{} bits flipped", flipped);
+        assert_ne!(digest(b"").0, digest(b"\0").0);
+    }
+
+    #[test]
+    fn test_memory_footprint() {
+        use alloc::format;
+        use alloc::string::String;
+
+        let empty: HashMap<u32, u32> = HashMap::new();
+        assert_eq!(empty.memory_footprint(), MapFootprint::default());
+
+        let before = live_bytes();
+        let mut map: HashMap<String, String> = HashMap::new();
+        for i in 0..300 {
+            map.insert(format!("key{}", i), format!("value{}", i * i));
+        }
+        for i in 0..100 {
+            map.remove(&format!("key{}", i));
+        }
+        // Mid-resize, both tables count.
+        while !map.is_resizing() {
+            map.insert(format!("key{}", map.len() + 100), String::new());
+        }
+        let footprint = map.memory_footprint_with(|k, v| k.capacity() + v.capacity());
+        assert_eq!(footprint.total(), live_bytes().wrapping_sub(before));
+
+        let slot = core::mem::size_of::<Slot<String, String>>();
+        let slots = map.table.slots.len() + map.old.slots.len();
+        assert_eq!(footprint.bucket_table_bytes, slots * slot);
+        assert_eq!(footprint.spare_capacity_bytes, (slots - map.len()) * slot);
+        let plain = map.memory_footprint();
+        assert_eq!(plain.entry_bytes, 0);
+        assert_eq!(plain.bucket_table_bytes, footprint.bucket_table_bytes);
+        assert_eq!(plain.spare_capacity_bytes, footprint.spare_capacity_bytes);
+
+        map.finish_resize();
+        map.shrink_to_fit();
+        let footprint = map.memory_footprint_with(|k, v| k.capacity() + v.capacity());
+        assert_eq!(footprint.total(), live_bytes().wrapping_sub(before));
+        drop(map);
+        assert_eq!(live_bytes(), before);
+    }
+
+    #[test]
+    fn test_replace_key() {
+        use alloc::string::String;
+
+        let mut interned: HashMap<String, u32> = HashMap::new();
+        let mut first = String::with_capacity(8);
+        first.push_str("path");
+        let first_ptr = first.as_ptr();
+        interned.insert(first, 1);
+
+        // Adopt an equal key with another allocation, keeping the value.
+        let mut second = String::with_capacity(64);
+        second.push_str("path");
+        let second_ptr = second.as_ptr();
+        let Entry::Occupied(entry) = interned.entry(second) else {
+            panic!("path is not interned")
+        };
+        let old = entry.replace_key();
+        assert_eq!((old.as_ptr(), old.capacity()), (first_ptr, 8));
+        assert_eq!(interned.len(), 1);
+        let (key, &value) = interned.get_key_value("path").unwrap();
+        assert_eq!((key.as_ptr(), key.capacity(), value), (second_ptr, 64, 1));
+
+        let third = String::from("path");
+        let third_ptr = third.as_ptr();
+        let Entry::Occupied(entry) = interned.entry(third) else {
+            panic!("path is not interned")
+        };
+        let (old, value) = entry.replace_entry(2);
+        assert_eq!((old.as_ptr(), value), (second_ptr, 1));
+        assert_eq!(interned.len(), 1);
+        let (key, &value) = interned.get_key_value("path").unwrap();
+        assert_eq!((key.as_ptr(), value), (third_ptr, 2));
+
+        // Also for an entry still in the old table during a resize.
+        let mut map = HashMap::with_capacity(50);
+        let full = map.capacity();
+        for i in 0..=full {
+            map.insert(i.to_string(), i);
+        }
+        assert!(map.is_resizing());
+        let Entry::Occupied(entry) = map.entry(full.to_string()) else {
+            unreachable!()
+        };
+        let _ = entry.replace_entry(0);
+        for i in (0..full).rev() {
+            let Entry::Occupied(entry) = map.entry(i.to_string()) else {
+                unreachable!()
+            };
+            assert_eq!(entry.replace_key(), i.to_string());
+        }
+        assert_eq!(map.len(), full + 1);
+        assert_eq!(map[&full.to_string()], 0);
+    }
+
+    #[test]
+    #[cfg(feature = "debug-validate")]
+    fn test_buckets() {
+        let mut map = HashMap::with_hasher(SimpleBuildHasher::with_seed(0));
+        for i in 0..200u32 {
+            map.insert(i, i * 2);
+        }
+        for i in 0..50 {
+            map.remove(&i);
+        }
+        map.finish_resize();
+
+        let buckets: Vec<_> = map.buckets().collect();
+        assert_eq!(buckets.len(), map.table.slots.len());
+        assert_eq!(
+            buckets.iter().map(BucketView::len).sum::<usize>(),
+            map.len()
+        );
+        for (i, bucket) in buckets.iter().enumerate() {
+            assert_eq!(bucket.index(), i);
+            assert_eq!(bucket.is_empty(), bucket.iter().count() == 0);
+        }
+        for key in 50..200 {
+            let bucket = &buckets[map.bucket_of(&key)];
+            assert!(bucket.iter().any(|(k, v)| (*k, *v) == (key, key * 2)));
+            for (k, _) in bucket.iter() {
+                assert_eq!(map.bucket_of(k), bucket.index());
+            }
+        }
+
+        // Two keys that collide share their bucket.
+        let (a, b) = (50..200)
+            .flat_map(|a| (a + 1..200).map(move |b| (a, b)))
+            .find(|&(a, b)| map.bucket_of(&a) == map.bucket_of(&b))
+            .unwrap();
+        let shared: Vec<_> = buckets[map.bucket_of(&a)].iter().map(|(k, _)| *k).collect();
+        assert!(shared.contains(&a) && shared.contains(&b));
+    }
+
+    #[test]
+    fn test_inline_promotion() {
+        use alloc::collections::BTreeMap;
+
+        // Without inline slots, the storage is as small as a `Vec`.
+        assert_eq!(
+            core::mem::size_of::<Slots<u64, u64, 0>>(),
+            core::mem::size_of::<Vec<Slot<u64, u64>>>()
+        );
+
+        let mut map: HashMap<u32, u32, SimpleBuildHasher, 8> =
+            HashMap::with_hasher_inline(SimpleBuildHasher::with_seed(5));
+        map.shrink_threshold(true);
+        let mut reference = BTreeMap::new();
+        let (mut promotions, mut demotions) = (0, 0);
+        let mut state = 0x9e37_79b9_7f4a_7c15;
+        for round in 0..8 {
+            // Grow well past the inline table, then empty the map again.
+            for grow in [true, false] {
+                while if grow {
+                    map.len() < 40
+                } else {
+                    !map.is_empty()
+                } {
+                    let was_inline = map.is_inline();
+                    let mut key = (xorshift(&mut state) % 64) as u32;
+                    if !grow {
+                        // Mostly remove keys that are present.
+                        let nth = key as usize % reference.len();
+                        key = *reference.keys().nth(nth).unwrap();
+                    }
+                    if grow == (xorshift(&mut state) % 4 != 0) {
+                        *map.entry(key).or_insert(0) += round;
+                        *reference.entry(key).or_insert(0) += round;
+                    } else {
+                        assert_eq!(map.remove(&key), reference.remove(&key));
+                    }
+                    assert_eq!(map.len(), reference.len());
+                    assert_eq!(map.get(&key), reference.get(&key));
+                    match (was_inline, map.is_inline()) {
+                        (true, false) => promotions += 1,
+                        (false, true) => demotions += 1,
+                        _ => {}
+                    }
+                }
+                map.check_invariants().unwrap();
+                let mut pairs: Vec<_> = map.iter().map(|(&k, &v)| (k, v)).collect();
+                pairs.sort_unstable();
+                assert!(pairs
+                    .into_iter()
+                    .eq(reference.iter().map(|(&k, &v)| (k, v))));
+            }
+        }
+        assert!(promotions >= 8 && demotions >= 8);
+
+        // Explicit shrinking moves the entries back in place too.
+        map.extend((0..20).map(|k| (k, k)));
+        assert!(!map.is_inline());
+        map.retain(|&k, _| k < 3);
+        map.shrink_to_fit();
+        assert!(map.is_inline());
+        let clone = map.clone();
+        assert!(clone.is_inline());
+        let mut pairs: Vec<_> = clone.into_iter().collect();
+        pairs.sort_unstable();
+        assert_eq!(pairs, [(0, 0), (1, 1), (2, 2)]);
+        assert_eq!(map.drain().count(), 3);
+        assert!(map.is_inline() && map.is_empty());
+    }
+
+    #[test]
+    fn test_inline_footprint() {
+        let before = allocations();
+        let mut map: HashMap<u32, u32, RandomSeededState, 8> = HashMap::new_inline();
+        for i in 0..4 {
+            assert_eq!(map.insert(i, i * 10), None);
+        }
+        *map.entry(2).or_default() += 1;
+        assert_eq!(map.remove(&0), Some(0));
+        map.insert(0, 0);
+        assert_eq!(map.get(&2), Some(&21));
+        assert_eq!(map.values().sum::<u32>(), 61);
+        assert!(map.is_inline());
+        assert_eq!(map.memory_footprint(), MapFootprint::default());
+        assert_eq!(allocations(), before);
+
+        // Past the inline table, the map allocates like any other.
+        let live = live_bytes();
+        map.extend((4..20).map(|i| (i, i)));
+        assert!(!map.is_inline());
+        assert_eq!(
+            map.memory_footprint().total(),
+            live_bytes().wrapping_sub(live)
+        );
+        assert!(allocations() > before);
+    }
+
+    /// Differential testing against `BTreeMap`: random operation sequences
+    /// are applied to both maps, which must agree after every step.
+    mod differential {
+        use super::*;
+        use alloc::collections::BTreeMap;
+        use alloc::format;
+        use alloc::string::String;
+        use core::panic::AssertUnwindSafe;
+
+        /// Keys are drawn from a small range, so that operations often hit
+        /// existing entries.
+        const KEYS: u16 = 48;
+
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        enum Op {
+            Insert(u16, u32),
+            /// Overwrites the `n`-th existing key, modulo the length.
+            Overwrite(usize, u32),
+            Remove(u16),
+            Get(u16),
+            /// Keeps the keys that are multiples of the given modulus.
+            Retain(u16),
+            Clear,
+            Reserve(usize),
+            Clone,
+            /// Iterates over the entries, incrementing every value.
+            Iterate,
+        }
+
+        fn generate(seed: u64, len: usize) -> Vec<Op> {
+            let mut state = seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1;
+            let mut next = move |n: u64| xorshift(&mut state) % n;
+            (0..len)
+                .map(|_| match next(100) {
+                    0..=39 => Op::Insert(next(KEYS as u64) as u16, next(1000) as u32),
+                    40..=49 => Op::Overwrite(next(KEYS as u64) as usize, next(1000) as u32),
+                    50..=74 => Op::Remove(next(KEYS as u64) as u16),
+                    75..=89 => Op::Get(next(KEYS as u64) as u16),
+                    90..=92 => Op::Retain(next(4) as u16 + 2),
+                    93 => Op::Clear,
+                    94..=95 => Op::Reserve(next(64) as usize),
+                    96..=97 => Op::Clone,
+                    _ => Op::Iterate,
+                })
+                .collect()
+        }
+
+        /// Applies `ops` to a map and to the oracle, and returns the index
+        /// of the first operation after which they disagree.
+        fn run<const INLINE: usize>(ops: &[Op], auto_shrink: bool) -> Result<(), (usize, String)> {
+            let mut map: HashMap<u16, u32, SimpleBuildHasher, INLINE> =
+                HashMap::with_hasher_inline(SimpleBuildHasher::with_seed(0x5eed));
+            map.shrink_threshold(auto_shrink);
+            let mut oracle = BTreeMap::new();
+            for (step, &op) in ops.iter().enumerate() {
+                let fail = |what: String| Err((step, what));
+                match op {
+                    Op::Insert(k, v) => {
+                        if map.insert(k, v) != oracle.insert(k, v) {
+                            return fail(format!("insert({}) returned another old value", k));
+                        }
+                    }
+                    Op::Overwrite(n, v) => {
+                        if let Some(&k) = oracle.keys().nth(n % oracle.len().max(1)) {
+                            if map.insert(k, v) != oracle.insert(k, v) {
+                                return fail(format!("overwrite({}) lost the old value", k));
+                            }
+                        }
+                    }
+                    Op::Remove(k) => {
+                        if map.remove(&k) != oracle.remove(&k) {
+                            return fail(format!("remove({}) disagrees", k));
+                        }
+                    }
+                    Op::Get(k) => {
+                        if map.get(&k) != oracle.get(&k) {
+                            return fail(format!("get({}) disagrees", k));
+                        }
+                    }
+                    Op::Retain(m) => {
+                        map.retain(|k, _| k % m == 0);
+                        oracle.retain(|k, _| k % m == 0);
+                    }
+                    Op::Clear => {
+                        map.clear();
+                        oracle.clear();
+                    }
+                    Op::Reserve(n) => {
+                        map.reserve(n);
+                        if map.capacity() < map.len() + n {
+                            return fail(format!("reserve({}) left too little room", n));
+                        }
+                    }
+                    Op::Clone => {
+                        let clone = map.clone();
+                        if clone != map {
+                            return fail(String::from("the clone differs"));
+                        }
+                        map = clone;
+                    }
+                    Op::Iterate => {
+                        if map.iter().count() != map.len() {
+                            return fail(String::from("iter() missed entries"));
+                        }
+                        map.values_mut().for_each(|v| *v += 1);
+                        oracle.values_mut().for_each(|v| *v += 1);
+                    }
+                }
+                if let Err(what) = check_equivalent(&map, &oracle) {
+                    return fail(what);
+                }
+            }
+            Ok(())
+        }
+
+        /// Compares every observable of `map` against `oracle`.
+        fn check_equivalent<const INLINE: usize>(
+            map: &HashMap<u16, u32, SimpleBuildHasher, INLINE>,
+            oracle: &BTreeMap<u16, u32>,
+        ) -> Result<(), String> {
+            if map.len() != oracle.len() || map.is_empty() != oracle.is_empty() {
+                return Err(format!("len {} instead of {}", map.len(), oracle.len()));
+            }
+            for k in 0..KEYS {
+                if map.get(&k) != oracle.get(&k) || map.contains_key(&k) != oracle.contains_key(&k)
+                {
+                    return Err(format!("lookup of {} disagrees", k));
+                }
+            }
+            let mut pairs: Vec<_> = map.iter().map(|(&k, &v)| (k, v)).collect();
+            pairs.sort_unstable();
+            if !pairs.iter().eq(oracle
+                .iter()
+                .map(|(&k, &v)| (k, v))
+                .collect::<Vec<_>>()
+                .iter())
+            {
+                return Err(String::from("sorted contents disagree"));
+            }
+            map.check_invariants()
+                .map_err(|violation| format!("invariant violated: {:?}", violation))
+        }
+
+        /// Returns `true` if `ops` fail, counting panics as failures.
+        fn fails<const INLINE: usize>(ops: &[Op], auto_shrink: bool) -> bool {
+            ::std::panic::catch_unwind(AssertUnwindSafe(|| run::<INLINE>(ops, auto_shrink)))
+                .map_or(true, |result| result.is_err())
+        }
+
+        /// Reduces a failing sequence by bisection: drops chunks of halving
+        /// sizes as long as the rest still fails, down to single operations.
+        fn shrink(mut ops: Vec<Op>, fails: impl Fn(&[Op]) -> bool) -> Vec<Op> {
+            let mut chunk = ops.len() / 2;
+            while chunk > 0 {
+                let mut start = 0;
+                while start < ops.len() {
+                    let end = (start + chunk).min(ops.len());
+                    let candidate: Vec<_> = [&ops[..start], &ops[end..]].concat();
+                    if fails(&candidate) {
+                        ops = candidate;
+                    } else {
+                        start = end;
+                    }
+                }
+                chunk /= 2;
+            }
+            ops
+        }
+
+        fn check_seeds<const INLINE: usize>() {
+            for seed in 0..16 {
+                let auto_shrink = seed % 2 == 1;
+                let ops = generate(seed, 500);
+                if fails::<INLINE>(&ops, auto_shrink) {
+                    let ops = shrink(ops, |ops| fails::<INLINE>(ops, auto_shrink));
+                    panic!(
+                        "seed {} (INLINE = {}, auto shrink {}) fails with {:?}: {:?}",
+                        seed,
+                        INLINE,
+                        auto_shrink,
+                        run::<INLINE>(&ops, auto_shrink).err(),
+                        ops
+                    );
+                }
+            }
+        }
+
+        #[test]
+        fn test_differential() {
+            check_seeds::<0>();
+            check_seeds::<8>();
+        }
+
+        #[test]
+        fn test_shrink() {
+            // A made-up bug: clearing the map after inserting key 3.
+            let buggy = |ops: &[Op]| {
+                let first = ops.iter().position(|op| matches!(op, Op::Insert(3, _)));
+                first.map_or(false, |i| ops[i..].contains(&Op::Clear))
+            };
+            let mut ops = generate(1, 300);
+            ops.push(Op::Insert(3, 7));
+            ops.push(Op::Clear);
+            let minimal = shrink(ops, buggy);
+            assert_eq!(minimal.len(), 2);
+            assert!(matches!(minimal[0], Op::Insert(3, _)));
+            assert_eq!(minimal[1], Op::Clear);
+
+            // Sequences of the same seed are identical.
+            assert_eq!(generate(9, 100), generate(9, 100));
+            assert!(!fails::<0>(&generate(9, 100), false));
+        }
     }
 }
@@ -0,0 +1,451 @@
+use core::borrow::Borrow;
+use core::fmt;
+use core::hash::{BuildHasher, Hash};
+use core::iter::FusedIterator;
+
+use crate::hashmap::{self, HashMap, RandomSeededState};
+
+/// A hash set implemented as a [`HashMap`] where the value is `()`.
+pub struct HashSet<K, S = RandomSeededState> {
+    map: HashMap<K, (), S>,
+}
+
+impl<K: Eq + Hash> HashSet<K, RandomSeededState> {
+    /// Creates an empty set.
+    pub fn new() -> Self {
+        Self {
+            map: HashMap::new(),
+        }
+    }
+
+    /// Creates an empty set with room for at least `cap` elements.
+    pub fn with_capacity(cap: usize) -> Self {
+        Self {
+            map: HashMap::with_capacity(cap),
+        }
+    }
+}
+
+impl<K, S> HashSet<K, S> {
+    /// Creates an empty set which will use the given hash builder to hash
+    /// keys.
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self {
+            map: HashMap::with_hasher(hash_builder),
+        }
+    }
+
+    /// Returns the number of elements in the set.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns `true` if the set contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// An iterator visiting all elements in arbitrary order.
+    pub fn iter(&self) -> Iter<'_, K> {
+        Iter {
+            inner: self.map.keys(),
+        }
+    }
+
+    /// Clears the set, removing all values.
+    pub fn clear(&mut self) {
+        self.map.clear()
+    }
+}
+
+impl<K: Eq + Hash, S: BuildHasher> HashSet<K, S> {
+    /// Adds a value to the set.
+    ///
+    /// Returns whether the value was newly inserted.
+    pub fn insert(&mut self, value: K) -> bool {
+        self.map.insert(value, ()).is_none()
+    }
+
+    /// Adds a value to the set, replacing the existing equal value, if any,
+    /// which is returned.
+    pub fn replace(&mut self, value: K) -> Option<K> {
+        match self.map.entry(value) {
+            hashmap::Entry::Occupied(entry) => Some(entry.replace_key()),
+            hashmap::Entry::Vacant(entry) => {
+                entry.insert(());
+                None
+            }
+        }
+    }
+
+    /// Returns a reference to the value in the set equal to the given one,
+    /// if any.
+    pub fn get<Q>(&self, value: &Q) -> Option<&K>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.map.get_key_value(value).map(|(k, _)| k)
+    }
+
+    /// Returns `true` if the set contains a value.
+    pub fn contains<Q>(&self, value: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.map.contains_key(value)
+    }
+
+    /// Removes a value from the set. Returns whether the value was present in
+    /// the set.
+    pub fn remove<Q>(&mut self, value: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.map.remove(value).is_some()
+    }
+
+    /// Removes and returns the value in the set equal to the given one, if
+    /// any.
+    pub fn take<Q>(&mut self, value: &Q) -> Option<K>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.map.remove_entry(value).map(|(k, _)| k)
+    }
+
+    /// Visits the values representing the difference, i.e., the values that
+    /// are in `self` but not in `other`.
+    pub fn difference<'a>(&'a self, other: &'a Self) -> impl Iterator<Item = &'a K> + 'a {
+        self.iter().filter(move |k| !other.contains(*k))
+    }
+
+    /// Visits the values representing the symmetric difference, i.e., the
+    /// values that are in `self` or in `other` but not in both.
+    pub fn symmetric_difference<'a>(&'a self, other: &'a Self) -> impl Iterator<Item = &'a K> + 'a {
+        self.difference(other).chain(other.difference(self))
+    }
+
+    /// Visits the values representing the intersection, i.e., the values
+    /// that are both in `self` and `other`.
+    pub fn intersection<'a>(&'a self, other: &'a Self) -> impl Iterator<Item = &'a K> + 'a {
+        self.iter().filter(move |k| other.contains(*k))
+    }
+
+    /// Visits the values representing the union, i.e., all the values in
+    /// `self` or `other`, without duplicates.
+    pub fn union<'a>(&'a self, other: &'a Self) -> impl Iterator<Item = &'a K> + 'a {
+        self.iter().chain(other.difference(self))
+    }
+
+    /// Returns `true` if `self` has no elements in common with `other`.
+    pub fn is_disjoint(&self, other: &Self) -> bool {
+        self.intersection(other).next().is_none()
+    }
+
+    /// Returns `true` if the set is a subset of another, i.e., `other`
+    /// contains at least all the values in `self`.
+    pub fn is_subset(&self, other: &Self) -> bool {
+        self.len() <= other.len() && self.iter().all(|k| other.contains(k))
+    }
+
+    /// Returns `true` if the set is a superset of another, i.e., `self`
+    /// contains at least all the values in `other`.
+    pub fn is_superset(&self, other: &Self) -> bool {
+        other.is_subset(self)
+    }
+
+    /// Retains only the elements specified by the predicate.
+    ///
+    /// In other words, removes all elements `k` for which `f(&k)` returns
+    /// `false`.
+    pub fn retain<F: FnMut(&K) -> bool>(&mut self, mut f: F) {
+        self.map.retain(|k, _| f(k));
+    }
+
+    /// Clears the set, returning all elements as an iterator. Keeps the
+    /// allocated table for reuse.
+    ///
+    /// If the returned iterator is dropped before being fully consumed, it
+    /// drops the remaining elements.
+    pub fn drain(&mut self) -> Drain<'_, K> {
+        Drain {
+            inner: self.map.drain(),
+        }
+    }
+}
+
+impl<K: Clone, S: Clone> Clone for HashSet<K, S> {
+    fn clone(&self) -> Self {
+        Self {
+            map: self.map.clone(),
+        }
+    }
+}
+
+impl<K: fmt::Debug, S> fmt::Debug for HashSet<K, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<K: Eq + Hash, S: BuildHasher> PartialEq for HashSet<K, S> {
+    fn eq(&self, other: &Self) -> bool {
+        self.map == other.map
+    }
+}
+
+impl<K: Eq + Hash, S: BuildHasher> Eq for HashSet<K, S> {}
+
+impl<K, S: Default> Default for HashSet<K, S> {
+    fn default() -> Self {
+        Self::with_hasher(S::default())
+    }
+}
+
+impl<K: Eq + Hash, S: BuildHasher + Default> FromIterator<K> for HashSet<K, S> {
+    fn from_iter<T: IntoIterator<Item = K>>(iter: T) -> Self {
+        Self {
+            map: iter.into_iter().map(|k| (k, ())).collect(),
+        }
+    }
+}
+
+impl<K: Eq + Hash, S: BuildHasher> Extend<K> for HashSet<K, S> {
+    fn extend<T: IntoIterator<Item = K>>(&mut self, iter: T) {
+        self.map.extend(iter.into_iter().map(|k| (k, ())));
+    }
+}
+
+impl<'a, K: Eq + Hash + Copy, S: BuildHasher> Extend<&'a K> for HashSet<K, S> {
+    fn extend<T: IntoIterator<Item = &'a K>>(&mut self, iter: T) {
+        self.extend(iter.into_iter().copied());
+    }
+}
+
+impl<K, S> IntoIterator for HashSet<K, S> {
+    type Item = K;
+    type IntoIter = IntoIter<K>;
+
+    fn into_iter(self) -> IntoIter<K> {
+        IntoIter {
+            inner: self.map.into_iter(),
+        }
+    }
+}
+
+impl<'a, K, S> IntoIterator for &'a HashSet<K, S> {
+    type Item = &'a K;
+    type IntoIter = Iter<'a, K>;
+
+    fn into_iter(self) -> Iter<'a, K> {
+        self.iter()
+    }
+}
+
+/// An iterator over the items of a [`HashSet`].
+///
+/// This is created by the [`iter`] method on [`HashSet`].
+///
+/// [`iter`]: HashSet::iter
+pub struct Iter<'a, K> {
+    inner: hashmap::Keys<'a, K, ()>,
+}
+
+impl<K> Clone for Iter<'_, K> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<'a, K> Iterator for Iter<'a, K> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<&'a K> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K> ExactSizeIterator for Iter<'_, K> {}
+impl<K> FusedIterator for Iter<'_, K> {}
+
+/// An owning iterator over the items of a [`HashSet`].
+///
+/// This is created by the [`into_iter`] method on [`HashSet`] (provided by
+/// the [`IntoIterator`] trait).
+///
+/// [`into_iter`]: IntoIterator::into_iter
+pub struct IntoIter<K> {
+    inner: hashmap::IntoIter<K, ()>,
+}
+
+impl<K> Iterator for IntoIter<K> {
+    type Item = K;
+
+    fn next(&mut self) -> Option<K> {
+        self.inner.next().map(|(k, _)| k)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K> ExactSizeIterator for IntoIter<K> {}
+impl<K> FusedIterator for IntoIter<K> {}
+
+/// A draining iterator over the items of a [`HashSet`].
+///
+/// This is created by the [`drain`] method on [`HashSet`].
+///
+/// [`drain`]: HashSet::drain
+pub struct Drain<'a, K> {
+    inner: hashmap::Drain<'a, K, ()>,
+}
+
+impl<K> Iterator for Drain<'_, K> {
+    type Item = K;
+
+    fn next(&mut self) -> Option<K> {
+        self.inner.next().map(|(k, _)| k)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K> ExactSizeIterator for Drain<'_, K> {}
+impl<K> FusedIterator for Drain<'_, K> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_contains_remove() {
+        let mut set = HashSet::new();
+        assert!(set.is_empty());
+        assert!(set.insert(3));
+        assert!(set.insert(5));
+        assert!(!set.insert(3));
+        assert_eq!(set.len(), 2);
+        assert!(set.contains(&3));
+        assert!(!set.contains(&4));
+
+        assert!(set.remove(&3));
+        assert!(!set.remove(&3));
+        assert_eq!(set.len(), 1);
+
+        set.clear();
+        assert!(set.is_empty());
+        assert!(!set.contains(&5));
+    }
+
+    #[test]
+    fn test_from_iter_with_repeats() {
+        let set: HashSet<u32> = [1, 2, 2, 3, 3, 3].into_iter().collect();
+        assert_eq!(set.len(), 3);
+        let mut items: Vec<_> = set.iter().copied().collect();
+        items.sort();
+        assert_eq!(items, [1, 2, 3]);
+
+        let mut other = set.clone();
+        other.extend([3, 4]);
+        assert_eq!(other.len(), 4);
+        assert_ne!(other, set);
+        assert!(other.remove(&4));
+        assert_eq!(other, set);
+
+        let mut owned: Vec<_> = other.into_iter().collect();
+        owned.sort();
+        assert_eq!(owned, [1, 2, 3]);
+        assert_eq!(format!("{:?}", HashSet::<u32>::new()), "{}");
+    }
+
+    fn sorted<'a>(iter: impl Iterator<Item = &'a u32>) -> Vec<u32> {
+        let mut v: Vec<_> = iter.copied().collect();
+        v.sort();
+        v
+    }
+
+    #[test]
+    fn test_set_algebra() {
+        let a: HashSet<u32> = [1, 2, 3, 4].into_iter().collect();
+        let b: HashSet<u32> = [3, 4, 5, 6].into_iter().collect();
+        let empty = HashSet::new();
+
+        assert_eq!(sorted(a.union(&b)), [1, 2, 3, 4, 5, 6]);
+        assert_eq!(sorted(a.intersection(&b)), [3, 4]);
+        assert_eq!(sorted(a.difference(&b)), [1, 2]);
+        assert_eq!(sorted(b.difference(&a)), [5, 6]);
+        assert_eq!(sorted(a.symmetric_difference(&b)), [1, 2, 5, 6]);
+
+        assert_eq!(sorted(a.union(&empty)), [1, 2, 3, 4]);
+        assert_eq!(sorted(empty.union(&a)), [1, 2, 3, 4]);
+        assert_eq!(a.intersection(&empty).count(), 0);
+        assert_eq!(sorted(a.difference(&empty)), [1, 2, 3, 4]);
+        assert_eq!(empty.difference(&a).count(), 0);
+        assert_eq!(sorted(empty.symmetric_difference(&a)), [1, 2, 3, 4]);
+
+        let c: HashSet<u32> = [3, 4].into_iter().collect();
+        assert!(c.is_subset(&a) && c.is_subset(&b));
+        assert!(a.is_superset(&c));
+        assert!(!a.is_subset(&b));
+        assert!(empty.is_subset(&a) && a.is_superset(&empty));
+        assert!(!a.is_disjoint(&b));
+        assert!(a.is_disjoint(&empty));
+        let d: HashSet<u32> = [7, 8].into_iter().collect();
+        assert!(a.is_disjoint(&d));
+    }
+
+    #[test]
+    fn test_bulk_operations() {
+        use alloc::string::String;
+
+        let mut set: HashSet<u32> = (0..100).collect();
+        set.retain(|k| k % 3 == 0);
+        assert_eq!(
+            sorted(set.iter()),
+            (0..100).filter(|k| k % 3 == 0).collect::<Vec<_>>()
+        );
+
+        let extra = [1, 2, 3];
+        set.extend(&extra);
+        assert_eq!(set.len(), 36);
+        let mut drained: Vec<_> = set.drain().collect();
+        drained.sort();
+        assert_eq!(drained.len(), 36);
+        assert_eq!(drained[..4], [0, 1, 2, 3]);
+        assert!(set.is_empty());
+        set.insert(7);
+        assert_eq!(set.drain().count(), 1);
+
+        // `take` and `replace` give back the stored allocation.
+        let mut names: HashSet<String> = HashSet::new();
+        let stored = String::from("init");
+        let ptr = stored.as_ptr();
+        names.insert(stored);
+        assert_eq!(names.get("init").map(|k| k.as_ptr()), Some(ptr));
+        let newer = String::from("init");
+        let newer_ptr = newer.as_ptr();
+        let old = names.replace(newer).unwrap();
+        assert_eq!(old.as_ptr(), ptr);
+        assert_eq!(names.len(), 1);
+        assert_eq!(names.replace(String::from("shell")), None);
+        let taken = names.take("init").unwrap();
+        assert_eq!(taken.as_ptr(), newer_ptr);
+        assert_eq!(names.take("init"), None);
+        assert_eq!(names.len(), 1);
+        assert!(names.contains("shell"));
+    }
+}
@@ -0,0 +1,391 @@
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::fmt;
+use core::hash::{BuildHasher, Hash};
+use core::iter::FusedIterator;
+use core::slice;
+
+use crate::hashmap::{buckets_for, RandomSeededState, RawTable, DEFAULT_LOAD_FACTOR};
+
+/// A hash map that iterates in insertion order.
+///
+/// Entries live in a dense vector in the order they were inserted, and a
+/// hash table of indices into it gives O(1) lookups. Replacing the value of
+/// an existing key keeps its position.
+///
+/// [`remove`] is O(1) but moves the last entry into the hole, which changes
+/// the order; [`shift_remove`] keeps the order at an O(n) cost.
+///
+/// [`remove`]: IndexMap::remove
+/// [`shift_remove`]: IndexMap::shift_remove
+#[derive(Clone)]
+pub struct IndexMap<K, V, S = RandomSeededState> {
+    entries: Vec<Entry<K, V>>,
+    /// Maps the cached hash of each key to its index in `entries`.
+    indices: RawTable<usize, ()>,
+    hash_builder: S,
+}
+
+#[derive(Clone)]
+struct Entry<K, V> {
+    hash: u64,
+    key: K,
+    value: V,
+}
+
+impl<K: Eq + Hash, V> IndexMap<K, V, RandomSeededState> {
+    /// Creates an empty map. Does not allocate until the first insertion.
+    pub fn new() -> Self {
+        Self::with_hasher(RandomSeededState::new())
+    }
+
+    /// Creates an empty map with room for at least `cap` elements.
+    pub fn with_capacity(cap: usize) -> Self {
+        Self::with_capacity_and_hasher(cap, RandomSeededState::new())
+    }
+}
+
+impl<K, V, S> IndexMap<K, V, S> {
+    /// Creates an empty map which will use the given hash builder to hash
+    /// keys.
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self::with_capacity_and_hasher(0, hash_builder)
+    }
+
+    /// Creates an empty map with room for at least `cap` elements, using
+    /// `hash_builder` to hash keys.
+    pub fn with_capacity_and_hasher(cap: usize, hash_builder: S) -> Self {
+        Self {
+            entries: Vec::with_capacity(cap),
+            indices: RawTable::with_slots(buckets_for(cap, DEFAULT_LOAD_FACTOR), 0),
+            hash_builder,
+        }
+    }
+
+    /// Returns the number of elements in the map.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the map contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the key-value pair at position `index` in insertion order.
+    pub fn get_index(&self, index: usize) -> Option<(&K, &V)> {
+        self.entries.get(index).map(|e| (&e.key, &e.value))
+    }
+
+    /// An iterator visiting all key-value pairs in insertion order.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            inner: self.entries.iter(),
+        }
+    }
+
+    /// Clears the map, removing all key-value pairs. Keeps the allocated
+    /// memory for reuse.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.indices.clear();
+    }
+}
+
+impl<K: Eq + Hash, V, S: BuildHasher> IndexMap<K, V, S> {
+    /// Returns the slot of `indices` referring to `key`.
+    fn find<Q>(&self, hash: u64, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq,
+    {
+        self.indices
+            .find_with(hash, |&i| self.entries[i].key.borrow() == key)
+    }
+
+    /// Returns the position of `key` in insertion order.
+    pub fn get_index_of<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let slot = self.find(self.hash_builder.hash_one(key), key)?;
+        Some(*self.indices.get(slot).0)
+    }
+
+    /// Returns a reference to the value corresponding to the key.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        Some(&self.entries[self.get_index_of(key)?].value)
+    }
+
+    /// Returns a mutable reference to the value corresponding to the key.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let index = self.get_index_of(key)?;
+        Some(&mut self.entries[index].value)
+    }
+
+    /// Returns `true` if the map contains a value for the specified key.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.get_index_of(key).is_some()
+    }
+
+    /// Inserts a key-value pair into the map.
+    ///
+    /// A new key is appended at the end of the order, and [`None`] is
+    /// returned. Otherwise the value is updated in place and the old value is
+    /// returned.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let hash = self.hash_builder.hash_one(&key);
+        if let Some(slot) = self.find(hash, &key) {
+            let index = *self.indices.get(slot).0;
+            return Some(core::mem::replace(&mut self.entries[index].value, value));
+        }
+        if self.indices.is_overloaded(DEFAULT_LOAD_FACTOR) {
+            self.rebuild_indices();
+        }
+        self.indices.insert_hashed(hash, self.entries.len(), ());
+        self.entries.push(Entry { hash, key, value });
+        None
+    }
+
+    /// Rebuilds the index table with room for as many new entries as there
+    /// are entries already, so that the O(n) rebuild is amortized.
+    fn rebuild_indices(&mut self) {
+        let slots = buckets_for((self.entries.len() + 1) * 2, DEFAULT_LOAD_FACTOR);
+        let salt = self.hash_builder.hash_one(slots);
+        let mut indices = RawTable::with_slots(slots, salt);
+        for (i, e) in self.entries.iter().enumerate() {
+            indices.insert_hashed(e.hash, i, ());
+        }
+        self.indices = indices;
+    }
+
+    /// Removes a key from the map, returning the value at the key if the key
+    /// was previously in the map.
+    ///
+    /// This moves the last entry into the position of the removed one, so
+    /// that the removal takes O(1) time but changes the order. Use
+    /// [`shift_remove`] to keep the order.
+    ///
+    /// [`shift_remove`]: IndexMap::shift_remove
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let slot = self.find(self.hash_builder.hash_one(key), key)?;
+        let (index, ()) = self.indices.take(slot);
+        let entry = self.entries.swap_remove(index);
+        if let Some(moved) = self.entries.get(index) {
+            let last = self.entries.len();
+            let slot = self.indices.find_with(moved.hash, |&i| i == last).unwrap();
+            *self.indices.key_mut(slot) = index;
+        }
+        Some(entry.value)
+    }
+
+    /// Removes a key from the map, returning the value at the key if the key
+    /// was previously in the map.
+    ///
+    /// The entries after the removed one move one position down, which keeps
+    /// the order but takes O(n) time.
+    pub fn shift_remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let slot = self.find(self.hash_builder.hash_one(key), key)?;
+        let (index, ()) = self.indices.take(slot);
+        let entry = self.entries.remove(index);
+        for i in self.indices.keys_mut() {
+            if *i > index {
+                *i -= 1;
+            }
+        }
+        Some(entry.value)
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug, S> fmt::Debug for IndexMap<K, V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, V, S: Default> Default for IndexMap<K, V, S> {
+    fn default() -> Self {
+        Self::with_hasher(S::default())
+    }
+}
+
+impl<K: Eq + Hash, V, S: BuildHasher + Default> FromIterator<(K, V)> for IndexMap<K, V, S> {
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
+        let mut map = Self::default();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K: Eq + Hash, V, S: BuildHasher> Extend<(K, V)> for IndexMap<K, V, S> {
+    fn extend<T: IntoIterator<Item = (K, V)>>(&mut self, iter: T) {
+        for (k, v) in iter {
+            self.insert(k, v);
+        }
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a IndexMap<K, V, S> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+/// An iterator over the entries of an [`IndexMap`], in insertion order.
+///
+/// This is created by the [`iter`] method on [`IndexMap`].
+///
+/// [`iter`]: IndexMap::iter
+pub struct Iter<'a, K, V> {
+    inner: slice::Iter<'a, Entry<K, V>>,
+}
+
+impl<K, V> Clone for Iter<'_, K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        self.inner.next().map(|e| (&e.key, &e.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Iter<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|e| (&e.key, &e.value))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+impl<K, V> FusedIterator for Iter<'_, K, V> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hashmap::tests::xorshift;
+    use crate::hashmap::SimpleBuildHasher;
+
+    fn keys(map: &IndexMap<u32, u32, SimpleBuildHasher>) -> Vec<u32> {
+        map.iter().map(|(&k, _)| k).collect()
+    }
+
+    #[test]
+    fn test_insertion_order() {
+        let mut map = IndexMap::with_hasher(SimpleBuildHasher::with_seed(0));
+        assert!(map.is_empty());
+        // Descending keys, so that the order cannot match any sorted one, and
+        // enough of them to resize the index table several times.
+        for i in (0..1000).rev() {
+            assert_eq!(map.insert(i, i * 10), None);
+        }
+        assert_eq!(map.len(), 1000);
+        assert!(keys(&map).into_iter().eq((0..1000).rev()));
+        assert_eq!(map.get_index(0), Some((&999, &9990)));
+        assert_eq!(map.get_index(999), Some((&0, &0)));
+        assert_eq!(map.get_index(1000), None);
+        assert_eq!(map.get_index_of(&0), Some(999));
+        assert_eq!(map.iter().next_back(), Some((&0, &0)));
+
+        // Replacing a value keeps the position.
+        assert_eq!(map.insert(500, 1), Some(5000));
+        assert_eq!(map.get_index(499), Some((&500, &1)));
+        *map.get_mut(&500).unwrap() = 2;
+        assert_eq!(map.get(&500), Some(&2));
+        assert!(!map.contains_key(&1000));
+
+        map.clear();
+        assert!(map.is_empty() && map.get(&500).is_none());
+        map.insert(7, 7);
+        assert_eq!(keys(&map), [7]);
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut map: IndexMap<u32, u32, _> = IndexMap::with_hasher(SimpleBuildHasher::with_seed(0));
+        map.extend((0..6).map(|i| (i, i)));
+        // The last entry takes the place of the removed one.
+        assert_eq!(map.remove(&1), Some(1));
+        assert_eq!(keys(&map), [0, 5, 2, 3, 4]);
+        assert_eq!(map.remove(&4), Some(4));
+        assert_eq!(keys(&map), [0, 5, 2, 3]);
+        assert_eq!(map.remove(&1), None);
+        // The following entries move down.
+        assert_eq!(map.shift_remove(&5), Some(5));
+        assert_eq!(keys(&map), [0, 2, 3]);
+        assert_eq!(map.shift_remove(&5), None);
+        for (i, k) in [0, 2, 3].iter().enumerate() {
+            assert_eq!(map.get_index_of(k), Some(i));
+            assert_eq!(map.get(k), Some(k));
+        }
+        map.insert(1, 1);
+        assert_eq!(keys(&map), [0, 2, 3, 1]);
+        assert_eq!(format!("{:?}", map), "{0: 0, 2: 2, 3: 3, 1: 1}");
+    }
+
+    #[test]
+    fn test_against_vec() {
+        let mut map = IndexMap::with_hasher(SimpleBuildHasher::with_seed(1));
+        let mut reference: Vec<(u32, u32)> = Vec::new();
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        for round in 0..20_000 {
+            let x = xorshift(&mut state);
+            let key = (x % 128) as u32;
+            let pos = reference.iter().position(|&(k, _)| k == key);
+            match x >> 60 {
+                0..=7 => {
+                    let old = pos.map(|i| core::mem::replace(&mut reference[i].1, round));
+                    if pos.is_none() {
+                        reference.push((key, round));
+                    }
+                    assert_eq!(map.insert(key, round), old);
+                }
+                8..=11 => {
+                    let old = pos.map(|i| reference.swap_remove(i).1);
+                    assert_eq!(map.remove(&key), old);
+                }
+                _ => {
+                    let old = pos.map(|i| reference.remove(i).1);
+                    assert_eq!(map.shift_remove(&key), old);
+                }
+            }
+            assert!(map
+                .iter()
+                .map(|(&k, &v)| (k, v))
+                .eq(reference.iter().copied()));
+        }
+    }
+}
@@ -22,6 +22,12 @@
 //!     - `alloc-buddy`: Use the buddy system allocator.
 //!     - `paging`: Enable page table manipulation.
 //!     - `tls`: Enable thread-local storage.
+//! - Collections
+//!     - `secure-hash`: Hash `HashMap` and `HashSet` keys with SipHash-1-3 by default.
+//!     - `deterministic-hash`: Seed new hash states with a fixed value instead of a random one,
+//!       for reproducible tests. Unsafe against untrusted keys.
+//!     - `debug-validate`: Check the internal invariants of `HashMap` after every mutation in
+//!       debug builds. Slow.
 //! - Task management
 //!     - `multitask`: Enable multi-threading support.
 //!     - `sched_fifo`: Use the FIFO cooperative scheduler.
@@ -49,29 +55,72 @@
 #![cfg_attr(all(not(test), not(doc)), no_std)]
 #![feature(doc_cfg)]
 #![feature(doc_auto_cfg)]
+#![feature(hasher_prefixfree_extras)]
 
 #[cfg(feature = "alloc")]
 extern crate alloc;
 #[cfg(feature = "alloc")]
 mod hashmap;
+#[cfg(feature = "alloc")]
+mod hashset;
+#[cfg(feature = "alloc")]
+mod indexmap;
+#[cfg(feature = "alloc")]
+mod lrucache;
+#[cfg(feature = "alloc")]
+mod sharedmap;
 
 #[cfg(feature = "alloc")]
 #[doc(no_inline)]
 pub use alloc::{collections as _alloc_collections, boxed, format, string, vec};
+/// Collection types, at the same paths as in `std`.
 #[cfg(feature = "alloc")]
 pub mod collections {
     pub use crate::hashmap::HashMap;
+    pub use crate::hashset::HashSet;
+    pub use crate::indexmap::IndexMap;
+    pub use crate::lrucache::LruCache;
+    pub use crate::sharedmap::SharedHashMap;
     pub use crate::_alloc_collections::*;
+
+    /// A hash map implemented with open addressing and linear probing.
+    pub mod hash_map {
+        pub use crate::hashmap::{
+            BucketStats, DefaultHasher, Drain, Entry, ExtractIf, HashMap, HashMapBuilder,
+            IntoIter, Iter, IterMut, Keys, MapFootprint, OccupiedEntry, RandomSeededState,
+            RandomState, SimpleBuildHasher, SimpleHasher, SipBuildHasher, SipHasher13,
+            VacantEntry, Values, ValuesMut, DETERMINISTIC_SEED,
+        };
+
+        #[cfg(feature = "debug-validate")]
+        pub use crate::hashmap::{BucketView, InvariantViolation};
+    }
+
+    /// A hash set implemented as a `HashMap` where the value is `()`.
+    pub mod hash_set {
+        pub use crate::hashset::{Drain, HashSet, IntoIter, Iter};
+    }
+
+    /// A hash map that iterates in insertion order.
+    pub mod index_map {
+        pub use crate::indexmap::{IndexMap, Iter};
+    }
+
+    /// A fixed-capacity cache evicting the least recently used entry.
+    pub mod lru_cache {
+        pub use crate::lrucache::{Iter, LruCache};
+    }
 }
 
 #[doc(no_inline)]
-pub use core::{arch, cell, cmp, hint, marker, mem, ops, ptr, slice, str};
+pub use core::{arch, cell, cmp, hint, marker, ops, ptr, slice, str};
 
 #[macro_use]
 mod macros;
 
 pub mod env;
 pub mod io;
+pub mod mem;
 pub mod os;
 pub mod process;
 pub mod sync;
@@ -0,0 +1,469 @@
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::fmt;
+use core::hash::{BuildHasher, Hash};
+use core::iter::FusedIterator;
+
+use crate::hashmap::{buckets_for, RandomSeededState, RawTable, DEFAULT_LOAD_FACTOR};
+
+/// Marks the end of the recency list.
+const NIL: usize = usize::MAX;
+
+/// A cache holding up to a fixed number of entries, which evicts the least
+/// recently used entry to make room for a new one.
+///
+/// Entries live in a single vector, linked into a recency list by indices
+/// rather than pointers, and a hash table of indices into it gives O(1)
+/// lookups. All memory is allocated at construction: inserting and evicting
+/// never allocate.
+pub struct LruCache<K, V, S = RandomSeededState> {
+    nodes: Vec<Node<K, V>>,
+    /// Maps the cached hash of each key to its index in `nodes`.
+    indices: RawTable<usize, ()>,
+    /// The most recently used node, or `NIL`.
+    head: usize,
+    /// The least recently used node, or `NIL`.
+    tail: usize,
+    cap: usize,
+    hash_builder: S,
+}
+
+struct Node<K, V> {
+    hash: u64,
+    key: K,
+    value: V,
+    /// The next more recently used node.
+    prev: usize,
+    /// The next less recently used node.
+    next: usize,
+}
+
+impl<K: Eq + Hash, V> LruCache<K, V, RandomSeededState> {
+    /// Creates an empty cache holding up to `cap` entries.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cap` is 0.
+    pub fn new(cap: usize) -> Self {
+        Self::with_hasher(cap, RandomSeededState::new())
+    }
+}
+
+impl<K, V, S> LruCache<K, V, S> {
+    /// Creates an empty cache holding up to `cap` entries, using
+    /// `hash_builder` to hash keys.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cap` is 0.
+    pub fn with_hasher(cap: usize, hash_builder: S) -> Self {
+        assert!(cap > 0, "LruCache capacity must be nonzero");
+        // Room for as many tombstones as entries, so that evictions purge the
+        // index table at most once every `cap` insertions.
+        let slots = buckets_for(
+            cap.checked_mul(2).expect("capacity overflow"),
+            DEFAULT_LOAD_FACTOR,
+        );
+        Self {
+            nodes: Vec::with_capacity(cap),
+            indices: RawTable::with_slots(slots, 0),
+            head: NIL,
+            tail: NIL,
+            cap,
+            hash_builder,
+        }
+    }
+
+    /// Returns the maximum number of entries the cache holds.
+    pub fn capacity(&self) -> usize {
+        self.cap
+    }
+
+    /// Returns the number of entries in the cache.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Returns `true` if the cache contains no entries.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// An iterator visiting all key-value pairs from the most to the least
+    /// recently used.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            nodes: &self.nodes,
+            next: self.head,
+            remaining: self.nodes.len(),
+        }
+    }
+
+    fn unlink(&mut self, i: usize) {
+        let (prev, next) = (self.nodes[i].prev, self.nodes[i].next);
+        match prev {
+            NIL => self.head = next,
+            _ => self.nodes[prev].next = next,
+        }
+        match next {
+            NIL => self.tail = prev,
+            _ => self.nodes[next].prev = prev,
+        }
+    }
+
+    fn push_front(&mut self, i: usize) {
+        self.nodes[i].prev = NIL;
+        self.nodes[i].next = self.head;
+        match self.head {
+            NIL => self.tail = i,
+            head => self.nodes[head].prev = i,
+        }
+        self.head = i;
+    }
+
+    /// Marks node `i` as the most recently used.
+    fn touch(&mut self, i: usize) {
+        if self.head != i {
+            self.unlink(i);
+            self.push_front(i);
+        }
+    }
+
+    /// Returns the slot of `indices` referring to node `i`.
+    fn slot_of(&self, i: usize) -> usize {
+        self.indices
+            .find_with(self.nodes[i].hash, |&j| j == i)
+            .expect("node missing from the index table")
+    }
+
+    fn insert_index(&mut self, hash: u64, i: usize) {
+        if self.indices.is_overloaded(DEFAULT_LOAD_FACTOR) {
+            // Only tombstones can overload the table: purge them, reusing
+            // its memory.
+            self.indices.clear();
+            for (j, node) in self.nodes.iter().enumerate() {
+                if j != i {
+                    self.indices.insert_hashed(node.hash, j, ());
+                }
+            }
+        }
+        self.indices.insert_hashed(hash, i, ());
+    }
+
+    /// Removes node `i`, moving the last node into its place.
+    fn remove_node(&mut self, i: usize) -> (K, V) {
+        self.unlink(i);
+        let slot = self.slot_of(i);
+        self.indices.take(slot);
+        let last = self.nodes.len() - 1;
+        if i != last {
+            let slot = self.slot_of(last);
+            *self.indices.key_mut(slot) = i;
+        }
+        let node = self.nodes.swap_remove(i);
+        if i != last {
+            let (prev, next) = (self.nodes[i].prev, self.nodes[i].next);
+            match prev {
+                NIL => self.head = i,
+                _ => self.nodes[prev].next = i,
+            }
+            match next {
+                NIL => self.tail = i,
+                _ => self.nodes[next].prev = i,
+            }
+        }
+        (node.key, node.value)
+    }
+
+    /// Removes and returns the least recently used entry.
+    pub fn pop_lru(&mut self) -> Option<(K, V)> {
+        match self.tail {
+            NIL => None,
+            tail => Some(self.remove_node(tail)),
+        }
+    }
+}
+
+impl<K: Eq + Hash, V, S: BuildHasher> LruCache<K, V, S> {
+    /// Returns the index of the node holding `key`.
+    fn find<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let hash = self.hash_builder.hash_one(key);
+        let slot = self
+            .indices
+            .find_with(hash, |&i| self.nodes[i].key.borrow() == key)?;
+        Some(*self.indices.get(slot).0)
+    }
+
+    /// Returns a reference to the value corresponding to the key, and marks
+    /// it as the most recently used.
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let i = self.find(key)?;
+        self.touch(i);
+        Some(&self.nodes[i].value)
+    }
+
+    /// Returns a mutable reference to the value corresponding to the key,
+    /// and marks it as the most recently used.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let i = self.find(key)?;
+        self.touch(i);
+        Some(&mut self.nodes[i].value)
+    }
+
+    /// Returns a reference to the value corresponding to the key, without
+    /// changing its recency.
+    pub fn peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        Some(&self.nodes[self.find(key)?].value)
+    }
+
+    /// Returns `true` if the cache contains the key, without changing its
+    /// recency.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.find(key).is_some()
+    }
+
+    /// Inserts a key-value pair as the most recently used entry.
+    ///
+    /// If the key was present, its value is replaced and the given key is
+    /// returned with the old value. Otherwise, if the cache was full, the
+    /// least recently used entry is evicted and returned.
+    pub fn insert(&mut self, key: K, value: V) -> Option<(K, V)> {
+        if let Some(i) = self.find(&key) {
+            self.touch(i);
+            return Some((key, core::mem::replace(&mut self.nodes[i].value, value)));
+        }
+        let hash = self.hash_builder.hash_one(&key);
+        let node = Node {
+            hash,
+            key,
+            value,
+            prev: NIL,
+            next: NIL,
+        };
+        if self.len() < self.cap {
+            self.nodes.push(node);
+            let i = self.len() - 1;
+            self.push_front(i);
+            self.insert_index(hash, i);
+            return None;
+        }
+        // Reuse the node of the evicted entry in place.
+        let i = self.tail;
+        self.unlink(i);
+        let slot = self.slot_of(i);
+        self.indices.take(slot);
+        let old = core::mem::replace(&mut self.nodes[i], node);
+        self.push_front(i);
+        self.insert_index(hash, i);
+        Some((old.key, old.value))
+    }
+
+    /// Removes a key from the cache, returning its value if the key was
+    /// present.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let i = self.find(key)?;
+        Some(self.remove_node(i).1)
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug, S> fmt::Debug for LruCache<K, V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a LruCache<K, V, S> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+/// An iterator over the entries of an [`LruCache`], from the most to the
+/// least recently used.
+///
+/// This is created by the [`iter`] method on [`LruCache`].
+///
+/// [`iter`]: LruCache::iter
+pub struct Iter<'a, K, V> {
+    nodes: &'a [Node<K, V>],
+    next: usize,
+    remaining: usize,
+}
+
+impl<K, V> Clone for Iter<'_, K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            nodes: self.nodes,
+            next: self.next,
+            remaining: self.remaining,
+        }
+    }
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        let node = self.nodes.get(self.next)?;
+        self.next = node.next;
+        self.remaining -= 1;
+        Some((&node.key, &node.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+impl<K, V> FusedIterator for Iter<'_, K, V> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hashmap::tests::xorshift;
+    use crate::hashmap::SimpleBuildHasher;
+
+    /// A trivially correct LRU cache: a vector from the most to the least
+    /// recently used.
+    struct Reference {
+        cap: usize,
+        entries: Vec<(u32, u32)>,
+    }
+
+    impl Reference {
+        fn position(&self, key: u32) -> Option<usize> {
+            self.entries.iter().position(|&(k, _)| k == key)
+        }
+
+        fn get(&mut self, key: u32) -> Option<u32> {
+            let entry = self.entries.remove(self.position(key)?);
+            self.entries.insert(0, entry);
+            Some(entry.1)
+        }
+
+        fn insert(&mut self, key: u32, value: u32) -> Option<(u32, u32)> {
+            if let Some(i) = self.position(key) {
+                let old = self.entries.remove(i).1;
+                self.entries.insert(0, (key, value));
+                return Some((key, old));
+            }
+            let evicted = (self.entries.len() == self.cap).then(|| self.entries.pop().unwrap());
+            self.entries.insert(0, (key, value));
+            evicted
+        }
+    }
+
+    type Cache = LruCache<u32, u32, SimpleBuildHasher>;
+
+    /// Applies operation `op` to both caches and checks that they agree.
+    fn step(cache: &mut Cache, reference: &mut Reference, op: u32, key: u32, value: u32) {
+        match op {
+            0 => assert_eq!(cache.insert(key, value), reference.insert(key, value)),
+            1 => assert_eq!(cache.get(&key).copied(), reference.get(key)),
+            2 => assert_eq!(cache.pop_lru(), reference.entries.pop()),
+            3 => {
+                let expected = reference
+                    .position(key)
+                    .map(|i| reference.entries.remove(i).1);
+                assert_eq!(cache.remove(&key), expected);
+            }
+            _ => {
+                let expected = reference.position(key).map(|i| reference.entries[i].1);
+                assert_eq!(cache.peek(&key).copied(), expected);
+            }
+        }
+        assert_eq!(cache.len(), reference.entries.len());
+        assert!(cache
+            .iter()
+            .map(|(&k, &v)| (k, v))
+            .eq(reference.entries.iter().copied()));
+    }
+
+    #[test]
+    fn test_eviction_order() {
+        let mut cache = LruCache::with_hasher(3, SimpleBuildHasher::with_seed(0));
+        assert!(cache.is_empty());
+        assert_eq!(cache.capacity(), 3);
+        assert_eq!(cache.insert(1, 10), None);
+        assert_eq!(cache.insert(2, 20), None);
+        assert_eq!(cache.insert(3, 30), None);
+        // Refreshes 1, so 2 is now the least recently used.
+        assert_eq!(cache.get(&1), Some(&10));
+        assert_eq!(cache.peek(&2), Some(&20));
+        assert_eq!(cache.insert(4, 40), Some((2, 20)));
+        assert!(!cache.contains_key(&2));
+        assert_eq!(cache.insert(1, 11), Some((1, 10)));
+        assert_eq!(format!("{:?}", cache), "{1: 11, 4: 40, 3: 30}");
+        assert_eq!(cache.pop_lru(), Some((3, 30)));
+        assert_eq!(cache.pop_lru(), Some((4, 40)));
+        assert_eq!(cache.pop_lru(), Some((1, 11)));
+        assert_eq!(cache.pop_lru(), None);
+    }
+
+    #[test]
+    fn test_exhaustive_capacity_3() {
+        // Every sequence of 6 operations among inserting or getting one of 4
+        // keys and popping: enough to evict every entry of a full cache in
+        // every order.
+        const OPS: u32 = 2 * 4 + 1;
+        for seq in 0..OPS.pow(6) {
+            let mut cache = LruCache::with_hasher(3, SimpleBuildHasher::with_seed(0));
+            let mut reference = Reference {
+                cap: 3,
+                entries: Vec::new(),
+            };
+            let mut code = seq;
+            for round in 0..6 {
+                let (op, key) = (code % OPS / 4, code % 4);
+                step(&mut cache, &mut reference, op, key, round);
+                code /= OPS;
+            }
+        }
+    }
+
+    #[test]
+    fn test_against_reference() {
+        for cap in [1, 2, 7, 64] {
+            let mut cache = LruCache::with_hasher(cap, SimpleBuildHasher::with_seed(cap as u128));
+            let mut reference = Reference {
+                cap,
+                entries: Vec::new(),
+            };
+            let mut state = 0x9e37_79b9_7f4a_7c15u64;
+            for _ in 0..20_000 {
+                let x = xorshift(&mut state);
+                let op = [0, 0, 0, 1, 1, 1, 2, 3, 4][(x >> 59) as usize % 9];
+                let key = (x % (cap as u64 * 2 + 1)) as u32;
+                step(&mut cache, &mut reference, op, key, x as u32);
+            }
+        }
+    }
+}
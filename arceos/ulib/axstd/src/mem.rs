@@ -0,0 +1,51 @@
+//! Basic functions for dealing with memory.
+//!
+//! Re-exports everything of [`core::mem`], plus statistics of the heap.
+
+#[doc(no_inline)]
+pub use core::mem::*;
+
+/// Usage of the heap, in bytes, as returned by [`heap_stats`].
+#[cfg(feature = "alloc")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HeapStats {
+    /// Bytes managed by the global byte allocator.
+    pub total: usize,
+    /// Bytes currently allocated.
+    pub used: usize,
+    /// Bytes that can still be allocated without growing the heap.
+    pub available: usize,
+}
+
+/// Returns the usage of the heap, as reported by the global byte allocator.
+///
+/// This is cheap: the allocator is locked just long enough to read its
+/// counters. The heap may still grow past `total` by taking pages from the
+/// page allocator.
+#[cfg(feature = "alloc")]
+pub fn heap_stats() -> HeapStats {
+    let stats = arceos_api::mem::ax_heap_stats();
+    HeapStats {
+        total: stats.total,
+        used: stats.used,
+        available: stats.available,
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn test_heap_stats() {
+        const LEN: usize = 1 << 20;
+        let before = heap_stats();
+        let buf: Vec<u8> = Vec::with_capacity(LEN);
+        let after = heap_stats();
+        assert!(after.used >= before.used + LEN);
+        assert!(after.used <= after.total);
+        drop(buf);
+        assert!(heap_stats().used < after.used);
+    }
+}
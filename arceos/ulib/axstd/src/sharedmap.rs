@@ -0,0 +1,164 @@
+use core::borrow::Borrow;
+use core::fmt;
+use core::hash::{BuildHasher, Hash};
+
+use crate::hashmap::{HashMap, RandomSeededState};
+use crate::sync::Mutex;
+
+/// A [`HashMap`] behind a [`Mutex`], to be shared between tasks.
+///
+/// Every method locks the map for the duration of a single operation, and
+/// the guard never escapes: [`with`] and [`with_mut`] lend the map to a
+/// closure instead. Keep these closures short, and do not access the same
+/// shared map from inside them, which would deadlock.
+///
+/// The map is `Send` and `Sync` as soon as the keys, values and hash state
+/// are `Send`.
+///
+/// [`with`]: SharedHashMap::with
+/// [`with_mut`]: SharedHashMap::with_mut
+pub struct SharedHashMap<K, V, S = RandomSeededState> {
+    inner: Mutex<HashMap<K, V, S>>,
+}
+
+impl<K: Eq + Hash, V> SharedHashMap<K, V, RandomSeededState> {
+    /// Creates an empty shared map.
+    pub fn new() -> Self {
+        Self::from_map(HashMap::new())
+    }
+}
+
+impl<K, V, S> SharedHashMap<K, V, S> {
+    /// Creates a shared map holding `map`.
+    pub const fn from_map(map: HashMap<K, V, S>) -> Self {
+        Self {
+            inner: Mutex::new(map),
+        }
+    }
+
+    /// Consumes the shared map, returning the map inside.
+    pub fn into_inner(self) -> HashMap<K, V, S> {
+        self.inner.into_inner()
+    }
+
+    /// Returns the number of elements in the map.
+    pub fn len(&self) -> usize {
+        self.inner.lock().len()
+    }
+
+    /// Returns `true` if the map contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.inner.lock().is_empty()
+    }
+
+    /// Locks the map and calls `f` with a shared reference to it.
+    pub fn with<R>(&self, f: impl FnOnce(&HashMap<K, V, S>) -> R) -> R {
+        f(&self.inner.lock())
+    }
+
+    /// Locks the map and calls `f` with a mutable reference to it.
+    pub fn with_mut<R>(&self, f: impl FnOnce(&mut HashMap<K, V, S>) -> R) -> R {
+        f(&mut self.inner.lock())
+    }
+}
+
+impl<K: Eq + Hash, V, S: BuildHasher> SharedHashMap<K, V, S> {
+    /// Inserts a key-value pair into the map, returning the old value of
+    /// the key, if any.
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        self.inner.lock().insert(key, value)
+    }
+
+    /// Removes a key from the map, returning its value, if any.
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.inner.lock().remove(key)
+    }
+
+    /// Returns a clone of the value of the key, if any. The map is unlocked
+    /// once the value is cloned.
+    pub fn get_cloned<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        V: Clone,
+    {
+        self.inner.lock().get(key).cloned()
+    }
+
+    /// Returns `true` if the map contains a value for the specified key.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.inner.lock().contains_key(key)
+    }
+}
+
+impl<K, V, S: Default> Default for SharedHashMap<K, V, S> {
+    fn default() -> Self {
+        Self::from_map(HashMap::default())
+    }
+}
+
+impl<K, V, S> From<HashMap<K, V, S>> for SharedHashMap<K, V, S> {
+    fn from(map: HashMap<K, V, S>) -> Self {
+        Self::from_map(map)
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug, S> fmt::Debug for SharedHashMap<K, V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.with(|map| f.debug_tuple("SharedHashMap").field(map).finish())
+    }
+}
+
+#[cfg(all(test, feature = "multitask"))]
+mod tests {
+    use super::*;
+    use alloc::sync::Arc;
+    use alloc::vec::Vec;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_concurrent_access() {
+        assert_send_sync::<SharedHashMap<alloc::string::String, Vec<u8>>>();
+
+        const TASKS: u32 = 4;
+        const KEYS: u32 = 1000;
+        let map = Arc::new(SharedHashMap::new());
+        let tasks: Vec<_> = (0..TASKS)
+            .map(|t| {
+                let map = map.clone();
+                crate::thread::spawn(move || {
+                    let base = t * KEYS;
+                    for k in base..base + KEYS {
+                        assert_eq!(map.insert(k, t), None);
+                        assert_eq!(map.get_cloned(&k), Some(t));
+                        // Read the other tasks' keys while they change.
+                        let _ = map.get_cloned(&((k + KEYS) % (TASKS * KEYS)));
+                    }
+                    for k in (base..base + KEYS).filter(|k| k % 2 == 1) {
+                        assert_eq!(map.remove(&k), Some(t));
+                        assert!(!map.contains_key(&k));
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.join().unwrap();
+        }
+
+        assert_eq!(map.len(), (TASKS * KEYS / 2) as usize);
+        map.with(|m| {
+            assert!(m.iter().all(|(k, t)| k % 2 == 0 && k / KEYS == *t));
+        });
+        map.with_mut(|m| m.retain(|_, t| *t == 0));
+        assert_eq!(Arc::try_unwrap(map).unwrap().into_inner().len(), 500);
+    }
+}